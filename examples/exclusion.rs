@@ -25,6 +25,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 }
 
 #[derive(Debug)]
+#[allow(dead_code)]
 struct ExcludedJob(String, ExclusionOption<u8>);
 
 impl Job for ExcludedJob {
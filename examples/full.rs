@@ -42,7 +42,7 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let stdin = std::io::stdin();
     let mut input = String::new();
-    while let Ok(_) = stdin.read_line(&mut input) {
+    while stdin.read_line(&mut input).is_ok() {
         if input == "\n" {
             return Ok(());
         }
@@ -74,7 +74,7 @@ impl Job for WaitJob {
     type Priority = u8;
 
     fn priority(&self) -> Self::Priority {
-        self.1.into()
+        self.1
     }
 }
 
@@ -88,7 +88,7 @@ impl FromStr for WaitJob {
     type Err = &'static str;
 
     fn from_str(line: &str) -> Result<WaitJob, Self::Err> {
-        let mut split = line.trim().split_whitespace();
+        let mut split = line.split_whitespace();
         if let Some(duration) = split.next() {
             match duration.parse() {
                 Ok(duration) => {
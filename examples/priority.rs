@@ -17,6 +17,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 }
 
 #[derive(Debug)]
+#[allow(dead_code)]
 struct PrioritisedJob(String, u8);
 
 impl Job for PrioritisedJob {
@@ -6,7 +6,7 @@ use std::time::Duration;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let runner = JobRunner::builder()
-        .limit_concurrency(|priority| (priority == 1).then(|| 1))
+        .limit_concurrency(|priority| (priority == 1).then_some(1))
         .build(4);
 
     for (i, priority) in (1..=10).zip([1, 2].iter().cycle()) {
@@ -19,6 +19,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 }
 
 #[derive(Debug)]
+#[allow(dead_code)]
 struct PrioritisedJob(String, u8);
 
 impl Job for PrioritisedJob {
@@ -0,0 +1,170 @@
+//! Where the runner gets the current time from when scheduling recurring jobs, see [`Clock`]
+
+use parking_lot::Mutex;
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Source of the current time used by the runner to schedule recurring jobs, see [`Builder::clock`](crate::Builder::clock)
+///
+/// The default is [`SystemClock`], for tests [`TestClock`] can be advanced by hand so that recurring jobs are scheduled deterministically.
+pub trait Clock: Send + Sync {
+    /// The current instant
+    fn now(&self) -> Instant;
+    /// The current time of day, which moves along with [`Clock::now`]. Cron schedules are matched against it and it is what's recorded when jobs are persisted, the default is [`SystemTime::now`]
+    fn system_time(&self) -> SystemTime {
+        SystemTime::now()
+    }
+    /// Register `wake` to be called whenever the clock jumps forward, so that the supervisor rechecks which recurring jobs are due rather than waiting for the real time to pass. It returns `false` once the runner has been dropped, after which it can be dropped too. Clocks which follow the real time can ignore this
+    fn on_advance(&self, _wake: Box<dyn Fn() -> bool + Send + Sync>) {}
+}
+
+/// [`Clock`] which follows the real time, using [`Instant::now`]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// [`Clock`] which only moves when it is advanced by hand, clones share the same time
+///
+/// ```
+/// use gaffer::{Clock, TestClock};
+/// use std::time::Duration;
+///
+/// let clock = TestClock::new();
+/// let start = clock.now();
+/// clock.advance(Duration::from_secs(60));
+/// assert_eq!(clock.now() - start, Duration::from_secs(60));
+/// ```
+#[derive(Clone, Default)]
+pub struct TestClock {
+    state: Arc<Mutex<TestClockState>>,
+}
+
+struct TestClockState {
+    now: Instant,
+    /// the time of day at `now`
+    time: SystemTime,
+    wakers: Vec<Box<dyn Fn() -> bool + Send + Sync>>,
+}
+
+impl Default for TestClockState {
+    fn default() -> Self {
+        Self {
+            now: Instant::now(),
+            time: SystemTime::now(),
+            wakers: vec![],
+        }
+    }
+}
+
+impl TestClock {
+    /// A clock starting at the current instant, which stays there until it is advanced
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A clock starting at the current instant, with the time of day `time`, so that cron schedules can be tested from a known time
+    pub fn at(time: SystemTime) -> Self {
+        let clock = Self::default();
+        clock.state.lock().time = time;
+        clock
+    }
+
+    /// Move the clock forward by `duration`, waking any runners which use it
+    pub fn advance(&self, duration: Duration) {
+        let mut state = self.state.lock();
+        state.now += duration;
+        state.time += duration;
+        // forget the runners which have been dropped
+        state.wakers.retain(|wake| wake());
+    }
+}
+
+impl Clock for TestClock {
+    fn now(&self) -> Instant {
+        self.state.lock().now
+    }
+
+    fn system_time(&self) -> SystemTime {
+        self.state.lock().time
+    }
+
+    fn on_advance(&self, wake: Box<dyn Fn() -> bool + Send + Sync>) {
+        self.state.lock().wakers.push(wake);
+    }
+}
+
+impl fmt::Debug for TestClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TestClock")
+            .field("now", &self.state.lock().now)
+            .field("time", &self.state.lock().time)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Job, JobRunner, NoExclusion, RecurrableJob};
+
+    use super::*;
+
+    #[derive(Clone)]
+    struct Nop;
+
+    impl Job for Nop {
+        type Exclusion = NoExclusion;
+
+        fn exclusion(&self) -> Self::Exclusion {
+            NoExclusion
+        }
+
+        type Priority = ();
+
+        fn priority(&self) -> Self::Priority {}
+
+        fn execute(self) {}
+    }
+
+    impl RecurrableJob for Nop {
+        fn matches(&self, _other: &Self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn dropped_runners_forgotten() {
+        let clock = TestClock::new();
+        let kept = JobRunner::<Nop>::builder()
+            .clock(clock.clone())
+            .build_manual(1);
+        let dropped = JobRunner::<Nop>::builder()
+            .clock(clock.clone())
+            .build_manual(1);
+        assert_eq!(clock.state.lock().wakers.len(), 2);
+        drop(dropped);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(clock.state.lock().wakers.len(), 1);
+        drop(kept);
+    }
+
+    /// recurring jobs set before the clock still use it
+    #[test]
+    fn clock_set_after_recurring() {
+        let clock = TestClock::new();
+        let mut runner = JobRunner::builder()
+            .set_recurring(Duration::from_secs(60), clock.now(), Nop)
+            .clock(clock.clone())
+            .build_manual(1);
+        assert_eq!(runner.run_until_idle(), 0);
+        clock.advance(Duration::from_secs(61));
+        assert_eq!(runner.run_until_idle(), 1);
+    }
+}
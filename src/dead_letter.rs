@@ -0,0 +1,263 @@
+//! Jobs which failed permanently or panicked, kept so that they can be inspected and requeued, see [`Builder::dead_letters`](crate::Builder::dead_letters)
+
+use parking_lot::Mutex;
+use std::{collections::VecDeque, fmt, sync::Arc, time::SystemTime};
+
+use crate::JobId;
+
+/// Why a job became a [`DeadLetter`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeadLetterReason {
+    /// The job failed with this error, formatted with [`Debug`](fmt::Debug), see [`JobOutcome::Failed`](crate::JobOutcome::Failed)
+    Failed(String),
+    /// The job asked to be retried, but it had used up the attempts of the [`RetryPolicy`](crate::RetryPolicy)
+    RetriesExhausted,
+    /// The job panicked, with this message if it panicked with a string
+    Panicked(Option<String>),
+}
+
+/// A job which failed permanently or panicked, see [`Builder::dead_letters`](crate::Builder::dead_letters)
+#[derive(Debug, Clone)]
+pub struct DeadLetter<J> {
+    /// The job, if it can be requeued. Only jobs which used up their retry attempts are kept, as jobs which fail or panic are consumed by executing them
+    pub job: Option<J>,
+    /// The id the job had in the runner
+    pub id: JobId,
+    /// How many times the job was executed
+    pub attempts: u32,
+    /// Why the job became a dead letter
+    pub reason: DeadLetterReason,
+    /// When the job was first executed, according to the runner's clock, see [`Builder::clock`](crate::Builder::clock)
+    pub first_attempt: SystemTime,
+    /// When the job last failed, according to the runner's clock
+    pub failed: SystemTime,
+}
+
+/// Where jobs which fail permanently or panic are sent, see [`Builder::dead_letters`](crate::Builder::dead_letters). Implement this to persist them, [`InMemoryDeadLetters`] keeps them in memory
+pub trait DeadLetterSink<J>: Send + Sync {
+    /// Store a dead letter, this is called on the worker which was executing the job
+    fn push(&self, letter: DeadLetter<J>);
+
+    /// Remove and return the dead letters for which `predicate` returns `true`, in the order they were pushed, see [`JobRunner::requeue_dead_letters`](crate::JobRunner::requeue_dead_letters)
+    fn take_where(&self, predicate: &mut dyn FnMut(&DeadLetter<J>) -> bool) -> Vec<DeadLetter<J>>;
+}
+
+/// [`DeadLetterSink`] which keeps up to a number of dead letters in memory, dropping the oldest once it is full. Clones share the same dead letters, so a clone can be kept to inspect them
+pub struct InMemoryDeadLetters<J> {
+    capacity: usize,
+    letters: Arc<Mutex<VecDeque<DeadLetter<J>>>>,
+}
+
+impl<J> InMemoryDeadLetters<J> {
+    /// Keep up to `capacity` dead letters
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            letters: Arc::default(),
+        }
+    }
+
+    /// The number of dead letters
+    pub fn len(&self) -> usize {
+        self.letters.lock().len()
+    }
+
+    /// Whether there are no dead letters
+    pub fn is_empty(&self) -> bool {
+        self.letters.lock().is_empty()
+    }
+
+    /// The dead letters, oldest first
+    pub fn letters(&self) -> Vec<DeadLetter<J>>
+    where
+        J: Clone,
+    {
+        self.letters.lock().iter().cloned().collect()
+    }
+}
+
+impl<J> Clone for InMemoryDeadLetters<J> {
+    fn clone(&self) -> Self {
+        Self {
+            capacity: self.capacity,
+            letters: self.letters.clone(),
+        }
+    }
+}
+
+impl<J: Send> DeadLetterSink<J> for InMemoryDeadLetters<J> {
+    fn push(&self, letter: DeadLetter<J>) {
+        let mut letters = self.letters.lock();
+        if letters.len() >= self.capacity {
+            if let Some(dropped) = letters.pop_front() {
+                log::warn!(
+                    "Dropping dead letter for job {} as there are already {}",
+                    dropped.id,
+                    self.capacity
+                );
+            }
+        }
+        if self.capacity > 0 {
+            letters.push_back(letter);
+        }
+    }
+
+    fn take_where(&self, predicate: &mut dyn FnMut(&DeadLetter<J>) -> bool) -> Vec<DeadLetter<J>> {
+        let mut letters = self.letters.lock();
+        let (taken, kept) = letters.drain(..).partition(|letter| predicate(letter));
+        *letters = kept;
+        taken.into()
+    }
+}
+
+/// Take the dead letters which have a job and for which `predicate` returns `true` from `sink`, passing their jobs to `send`. If `send` gives a job back, it is put back in the sink. Returns how many jobs were sent
+pub(crate) fn requeue<J>(
+    sink: &dyn DeadLetterSink<J>,
+    mut predicate: impl FnMut(&DeadLetter<J>) -> bool,
+    mut send: impl FnMut(J) -> Result<(), J>,
+) -> usize {
+    let mut requeued = 0;
+    for mut letter in sink.take_where(&mut |letter| letter.job.is_some() && predicate(letter)) {
+        if let Some(job) = letter.job.take() {
+            match send(job) {
+                Ok(()) => requeued += 1,
+                Err(job) => {
+                    letter.job = Some(job);
+                    sink.push(letter);
+                }
+            }
+        }
+    }
+    requeued
+}
+
+impl<J> fmt::Debug for InMemoryDeadLetters<J> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InMemoryDeadLetters")
+            .field("capacity", &self.capacity)
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{sync::Mutex, time::Duration};
+
+    use crate::{FallibleJob, Job, JobOutcome, JobRunner, NoExclusion, RetryPolicy, TestClock};
+
+    use super::*;
+
+    /// fails this many more times before it succeeds
+    #[derive(Clone)]
+    struct Flaky(u32, Arc<Mutex<String>>);
+
+    impl Job for Flaky {
+        type Exclusion = NoExclusion;
+
+        fn exclusion(&self) -> Self::Exclusion {
+            NoExclusion
+        }
+
+        type Priority = ();
+
+        fn priority(&self) -> Self::Priority {}
+
+        fn execute(self) {
+            let _ = self.try_execute();
+        }
+    }
+
+    impl FallibleJob for Flaky {
+        type Error = ();
+
+        fn try_execute(self) -> JobOutcome<Self, Self::Error> {
+            self.1.lock().unwrap().push_str(&self.0.to_string());
+            if self.0 > 0 {
+                JobOutcome::Retry(Flaky(self.0 - 1, self.1))
+            } else {
+                JobOutcome::Done
+            }
+        }
+    }
+
+    fn letter(attempts: u32) -> DeadLetter<()> {
+        DeadLetter {
+            job: Some(()),
+            id: JobId::next(),
+            attempts,
+            reason: DeadLetterReason::RetriesExhausted,
+            first_attempt: SystemTime::now(),
+            failed: SystemTime::now(),
+        }
+    }
+
+    #[test]
+    fn bounded() {
+        let letters = InMemoryDeadLetters::new(2);
+        letters.push(letter(1));
+        letters.push(letter(2));
+        letters.push(letter(3));
+        assert_eq!(letters.len(), 2);
+        let taken = letters.take_where(&mut |letter| letter.attempts == 3);
+        assert_eq!(taken.len(), 1);
+        assert_eq!(
+            letters
+                .letters()
+                .iter()
+                .map(|letter| letter.attempts)
+                .collect::<Vec<_>>(),
+            vec![2]
+        );
+    }
+
+    #[test]
+    fn dead_letters_requeued() {
+        let executed = Arc::default();
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+        let clock = TestClock::at(start);
+        let dead_letters = InMemoryDeadLetters::new(10);
+        let mut runner = JobRunner::builder()
+            .clock(clock.clone())
+            .retry_policy(
+                RetryPolicy::default()
+                    .max_attempts(2)
+                    .backoff(Duration::from_secs(1), Duration::from_secs(60))
+                    .jitter(false),
+            )
+            .dead_letters(dead_letters.clone())
+            .build_manual(1);
+        runner.send(Flaky(4, Arc::clone(&executed))).unwrap();
+        assert_eq!(runner.run_until_idle(), 1);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(runner.run_until_idle(), 1);
+        let letters = dead_letters.letters();
+        assert_eq!(letters.len(), 1);
+        assert_eq!(letters[0].attempts, 2);
+        assert_eq!(letters[0].reason, DeadLetterReason::RetriesExhausted);
+        assert_eq!(letters[0].first_attempt, start);
+        assert_eq!(letters[0].failed, start + Duration::from_secs(1));
+        let first_id = letters[0].id;
+
+        // the dead letter has the job which asked to be retried last, which is
+        // requeued as a new job with all of its attempts again
+        assert_eq!(
+            runner.requeue_dead_letters(|letter| letter.attempts == 2),
+            1
+        );
+        assert!(dead_letters.is_empty());
+        assert_eq!(runner.run_until_idle(), 1);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(runner.run_until_idle(), 1);
+        let letters = dead_letters.letters();
+        assert_eq!(letters.len(), 1);
+        assert_eq!(letters[0].attempts, 2);
+        assert_ne!(letters[0].id, first_id);
+        assert_eq!(letters[0].first_attempt, start + Duration::from_secs(1));
+
+        assert_eq!(runner.requeue_dead_letters(|_| true), 1);
+        assert_eq!(runner.run_until_idle(), 1);
+        assert!(dead_letters.is_empty());
+        assert_eq!(*executed.lock().unwrap(), "43210");
+    }
+}
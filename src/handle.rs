@@ -0,0 +1,518 @@
+//! Tracking of jobs once they have been sent to the runner, see [`JobRunner::send_tracked`](crate::JobRunner::send_tracked)
+
+use parking_lot::Mutex;
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Weak,
+    },
+    thread,
+    time::{Instant, SystemTime},
+};
+
+#[cfg(feature = "journal")]
+use crate::journal::{JournalEntry, Started};
+use crate::{
+    source::{schedule::CompletionToken, util::PriorityQueue},
+    Job, MergeResult,
+};
+
+/// Identifies a job which has been sent to a runner
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct JobId(u64);
+
+/// The next id to give out
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+impl JobId {
+    /// Greater than any id which is given out, for use as a bound
+    pub(crate) const MAX: JobId = JobId(u64::MAX);
+
+    pub(crate) fn next() -> Self {
+        Self(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// An id which was given out before a restart, the ids given out from now on are greater than it so that they don't clash with it
+    #[cfg(feature = "journal")]
+    pub(crate) fn restored(id: u64) -> Self {
+        NEXT_ID.fetch_max(id.saturating_add(1), Ordering::Relaxed);
+        Self(id)
+    }
+}
+
+impl fmt::Display for JobId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// The status of a job which has been sent to the runner
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum JobStatus {
+    /// Waiting in the queue
+    Queued,
+    /// Merged into another job in the queue, the job with this id will do the work of this one, see [`JobHandle::resolved_status`]
+    Merged(JobId),
+    /// Being executed by the worker with this index
+    Running(usize),
+    /// Execution completed
+    Completed,
+    /// Execution panicked
+    Panicked,
+    /// Execution failed and won't be retried, see [`FallibleJob`](crate::FallibleJob)
+    Failed,
+    /// Cancelled before it started, it won't be executed
+    Cancelled,
+    /// The job's deadline passed before it started, it won't be executed, see [`Job::deadline`]
+    Expired,
+}
+
+/// Handle on a job which has been sent to the runner, to check it's status or to cancel it
+pub struct JobHandle<J: Job + 'static> {
+    id: JobId,
+    tracker: Tracker,
+    queue: Weak<Mutex<PriorityQueue<QueuedJob<J>>>>,
+}
+
+impl<J: Job + 'static> JobHandle<J> {
+    pub(crate) fn new(
+        job: &QueuedJob<J>,
+        tracker: Tracker,
+        queue: Weak<Mutex<PriorityQueue<QueuedJob<J>>>>,
+    ) -> Self {
+        Self {
+            id: job.id,
+            tracker,
+            queue,
+        }
+    }
+
+    /// The id of the job
+    pub fn id(&self) -> JobId {
+        self.id
+    }
+
+    /// The status of this job, if it has been merged this will be [`JobStatus::Merged`] and [`JobHandle::resolved_status`] will give the status of the job it was merged into
+    pub fn status(&self) -> JobStatus {
+        self.tracker.0.lock().status
+    }
+
+    /// The status of this job, or if it has been merged, the status of the job which it was merged into
+    pub fn resolved_status(&self) -> JobStatus {
+        self.tracker.resolve().0.lock().status
+    }
+
+    /// Cancel the job if it hasn't started yet, returns whether it was cancelled. Jobs which have been merged into other jobs can't be cancelled, but cancelling a job also cancels any jobs that were merged into it
+    pub fn cancel(&self) -> bool {
+        if !self.tracker.cancel() {
+            return false;
+        }
+        if let Some(queue) = self.queue.upgrade() {
+            queue.lock().retain(|queued| {
+                let keep = queued.id != self.id;
+                if !keep {
+                    queued.mark_removed();
+                }
+                keep
+            });
+        }
+        true
+    }
+}
+
+impl<J: Job + 'static> fmt::Debug for JobHandle<J> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JobHandle")
+            .field("id", &self.id)
+            .field("status", &self.status())
+            .finish()
+    }
+}
+
+/// Shared status of a job, updated by the runner and read by the [`JobHandle`]
+#[derive(Clone)]
+pub(crate) struct Tracker(Arc<Mutex<TrackerState>>);
+
+struct TrackerState {
+    status: JobStatus,
+    merged_into: Option<Tracker>,
+}
+
+impl Tracker {
+    fn new() -> Self {
+        Self(Arc::new(Mutex::new(TrackerState {
+            status: JobStatus::Queued,
+            merged_into: None,
+        })))
+    }
+
+    /// follow the merges to the tracker of the job which will actually be executed
+    fn resolve(&self) -> Tracker {
+        let mut tracker = self.clone();
+        loop {
+            let next = tracker.0.lock().merged_into.clone();
+            if let Some(next) = next {
+                tracker = next;
+            } else {
+                return tracker;
+            }
+        }
+    }
+
+    fn merged(&self, id: JobId, into: &Tracker) {
+        let mut state = self.0.lock();
+        state.status = JobStatus::Merged(id);
+        state.merged_into = Some(into.clone());
+    }
+
+    /// mark the job as running, returns false if it was cancelled and so shouldn't be run
+    fn start(&self, worker_index: usize) -> bool {
+        let mut state = self.0.lock();
+        if state.status == JobStatus::Cancelled {
+            return false;
+        }
+        state.status = JobStatus::Running(worker_index);
+        true
+    }
+
+    fn cancel(&self) -> bool {
+        let mut state = self.0.lock();
+        if state.status == JobStatus::Queued {
+            state.status = JobStatus::Cancelled;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expire(&self) {
+        let mut state = self.0.lock();
+        if state.status == JobStatus::Queued {
+            state.status = JobStatus::Expired;
+        }
+    }
+}
+
+/// Marks the job as finished when dropped, so panics are also recorded
+struct Finished(Option<Tracker>);
+
+impl Finished {
+    /// The job is going to be retried, so it is queued again rather than finished
+    fn requeue(mut self) -> Option<Tracker> {
+        let tracker = self.0.take();
+        if let Some(tracker) = &tracker {
+            tracker.0.lock().status = JobStatus::Queued;
+        }
+        tracker
+    }
+
+    /// The job failed and won't be retried
+    fn failed(mut self) {
+        if let Some(tracker) = self.0.take() {
+            tracker.0.lock().status = JobStatus::Failed;
+        }
+    }
+}
+
+impl Drop for Finished {
+    fn drop(&mut self) {
+        if let Some(tracker) = &self.0 {
+            tracker.0.lock().status = if thread::panicking() {
+                JobStatus::Panicked
+            } else {
+                JobStatus::Completed
+            };
+        }
+    }
+}
+
+/// What happened when a job was executed, see [`QueuedJob::attempt`]
+pub(crate) enum Executed<J> {
+    Done,
+    /// the job should be enqueued again
+    Retry(J),
+    /// the job failed and won't be retried
+    Failed,
+}
+
+/// A job in the runner, along with it's identity and tracking
+pub(crate) struct QueuedJob<J> {
+    pub(crate) job: J,
+    pub(crate) id: JobId,
+    tracker: Option<Tracker>,
+    /// dropped once the job has finished, to notify recurring jobs which are anchored to its completion
+    completions: Vec<CompletionToken>,
+    #[cfg(feature = "journal")]
+    pub(crate) journal: Option<JournalEntry>,
+    /// how many times the job has already been executed, before being retried
+    pub(crate) attempts: u32,
+    /// when the job was first executed, if it has been
+    pub(crate) first_attempt: Option<SystemTime>,
+}
+
+impl<J: Job> QueuedJob<J> {
+    /// A job which isn't tracked
+    pub fn new(job: J) -> Self {
+        Self::with_id(job, JobId::next())
+    }
+
+    /// A job which isn't tracked, with an id it was given before, such as a job restored from the journal
+    pub fn with_id(job: J, id: JobId) -> Self {
+        Self {
+            job,
+            id,
+            tracker: None,
+            completions: vec![],
+            #[cfg(feature = "journal")]
+            journal: None,
+            attempts: 0,
+            first_attempt: None,
+        }
+    }
+
+    /// A job which is tracked, along with it's tracker
+    pub fn tracked(job: J) -> (Self, Tracker) {
+        let tracker = Tracker::new();
+        let queued = Self {
+            job,
+            id: JobId::next(),
+            tracker: Some(tracker.clone()),
+            completions: vec![],
+            #[cfg(feature = "journal")]
+            journal: None,
+            attempts: 0,
+            first_attempt: None,
+        };
+        (queued, tracker)
+    }
+
+    /// The job, which won't be executed by the runner
+    pub fn into_inner(self) -> J {
+        #[cfg(feature = "journal")]
+        if let Some(journal) = &self.journal {
+            journal.finish();
+        }
+        self.job
+    }
+
+    /// Notify `token` when this job has finished, or when it is dropped without being executed
+    pub fn on_completion(&mut self, token: CompletionToken) {
+        self.completions.push(token);
+    }
+
+    /// Record that the job won't be executed, for when it is being removed from the queue and dropped
+    pub fn mark_removed(&self) {
+        self.mark_cancelled();
+        #[cfg(feature = "journal")]
+        if let Some(journal) = &self.journal {
+            journal.finish();
+        }
+    }
+
+    /// The job won't be executed, it is cancelled and returned. It stays in the journal, as the runner is shutting down and it would be executed when restarted
+    pub fn cancel(self) -> J {
+        self.mark_cancelled();
+        self.job
+    }
+
+    /// Whether the job's deadline has passed by `now`
+    pub fn is_expired(&self, now: Instant) -> bool {
+        self.job.deadline().is_some_and(|deadline| now > deadline)
+    }
+
+    /// The job's deadline passed before it started, so it won't be executed by the runner
+    pub fn expire(self) -> J {
+        if let Some(tracker) = &self.tracker {
+            tracker.expire();
+        }
+        self.into_inner()
+    }
+
+    /// Record that the job won't be executed, for when it is being removed from the queue
+    pub fn mark_cancelled(&self) {
+        if let Some(tracker) = &self.tracker {
+            tracker.cancel();
+        }
+    }
+
+    /// Attempt to merge this job into `that` using `merge_fn`, recording the merge in this job's tracker
+    pub fn merge(
+        self,
+        that: &mut QueuedJob<J>,
+        merge_fn: fn(J, &mut J) -> MergeResult<J>,
+    ) -> MergeResult<Self> {
+        if self.is_cancelled() || that.is_cancelled() {
+            // the work of a cancelled job won't be done, so it can't be merged
+            return MergeResult::NotMerged(self);
+        }
+        let QueuedJob {
+            job,
+            id,
+            tracker,
+            completions,
+            #[cfg(feature = "journal")]
+            journal,
+            attempts,
+            first_attempt,
+        } = self;
+        match merge_fn(job, &mut that.job) {
+            MergeResult::Success => {
+                if let Some(tracker) = tracker {
+                    tracker.merged(that.id, that.tracker.get_or_insert_with(Tracker::new));
+                }
+                // this job's work is now done by `that`, so it completes when `that` does
+                that.completions.extend(completions);
+                MergeResult::Success
+            }
+            MergeResult::NotMerged(job) => MergeResult::NotMerged(QueuedJob {
+                job,
+                id,
+                tracker,
+                completions,
+                #[cfg(feature = "journal")]
+                journal,
+                attempts,
+                first_attempt,
+            }),
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.tracker
+            .as_ref()
+            .is_some_and(|tracker| tracker.0.lock().status == JobStatus::Cancelled)
+    }
+
+    /// Execute the job on the worker with index `worker_index`, unless it has been cancelled
+    pub fn run(self, worker_index: usize) {
+        self.attempt(worker_index, |job| {
+            job.execute();
+            Executed::Done
+        });
+    }
+
+    /// Execute the job with `execute` like [`QueuedJob::run`]. If it is to be retried, it is returned to be enqueued again, keeping its identity, tracking and place in the journal
+    pub fn attempt(
+        self,
+        worker_index: usize,
+        execute: impl FnOnce(J) -> Executed<J>,
+    ) -> Option<Self> {
+        let QueuedJob {
+            job,
+            id,
+            tracker,
+            completions,
+            #[cfg(feature = "journal")]
+            journal,
+            attempts,
+            first_attempt,
+        } = self;
+        #[cfg(feature = "journal")]
+        let journal = journal.map(JournalEntry::start);
+        let finished = if let Some(tracker) = tracker {
+            if !tracker.start(worker_index) {
+                return None;
+            }
+            Finished(Some(tracker))
+        } else {
+            Finished(None)
+        };
+        match execute(job) {
+            Executed::Done => None,
+            Executed::Retry(job) => Some(QueuedJob {
+                job,
+                id,
+                tracker: finished.requeue(),
+                completions,
+                #[cfg(feature = "journal")]
+                journal: journal.map(Started::retry),
+                attempts: attempts + 1,
+                first_attempt,
+            }),
+            Executed::Failed => {
+                finished.failed();
+                None
+            }
+        }
+    }
+}
+
+impl<J: Job> Job for QueuedJob<J> {
+    type Exclusion = J::Exclusion;
+
+    fn exclusion(&self) -> Self::Exclusion {
+        self.job.exclusion()
+    }
+
+    type Priority = J::Priority;
+
+    fn priority(&self) -> Self::Priority {
+        self.job.priority()
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.job.deadline()
+    }
+
+    fn execute(self) {
+        self.job.execute()
+    }
+}
+
+impl<J: fmt::Debug> fmt::Debug for QueuedJob<J> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.job.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::NoExclusion;
+
+    use super::*;
+
+    #[derive(Debug)]
+    struct Tester;
+
+    impl Job for Tester {
+        type Exclusion = NoExclusion;
+
+        fn exclusion(&self) -> Self::Exclusion {
+            NoExclusion
+        }
+
+        type Priority = ();
+
+        fn priority(&self) -> Self::Priority {}
+
+        fn execute(self) {}
+    }
+
+    fn merge(_this: Tester, _that: &mut Tester) -> MergeResult<Tester> {
+        MergeResult::Success
+    }
+
+    #[test]
+    fn merges_resolve() {
+        let (a, a_tracker) = QueuedJob::tracked(Tester);
+        let (mut b, b_tracker) = QueuedJob::tracked(Tester);
+        let mut c = QueuedJob::new(Tester);
+        let (b_id, c_id) = (b.id, c.id);
+        assert!(matches!(a.merge(&mut b, merge), MergeResult::Success));
+        assert!(matches!(b.merge(&mut c, merge), MergeResult::Success));
+        assert_eq!(a_tracker.0.lock().status, JobStatus::Merged(b_id));
+        assert_eq!(b_tracker.0.lock().status, JobStatus::Merged(c_id));
+        c.run(3);
+        assert_eq!(a_tracker.resolve().0.lock().status, JobStatus::Completed);
+    }
+
+    #[test]
+    fn cancelled_not_run() {
+        let (a, tracker) = QueuedJob::tracked(Tester);
+        assert!(tracker.cancel());
+        assert!(!tracker.cancel());
+        a.run(0);
+        assert_eq!(tracker.0.lock().status, JobStatus::Cancelled);
+    }
+}
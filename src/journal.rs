@@ -0,0 +1,430 @@
+//! Durable journal of the jobs in the runner, so that they can be restored after a crash or restart, see [`Builder::journal`](crate::Builder::journal)
+//!
+//! The journal is a file with a line for each change to the jobs in the runner, `E #id json` when a job is enqueued, `M #id json` when another job is merged into it, `S #id` when it starts and `C #id` when it is completed or removed. Replaying it gives the jobs which were never completed. Each record is synced to disk as it is written, and ids carry on from the highest in the journal, so that restored jobs keep their ids without clashing with new ones.
+
+use parking_lot::Mutex;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::{btree_map::Entry, BTreeMap},
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use crate::{
+    handle::{JobId, QueuedJob},
+    Job, MergeResult,
+};
+
+/// Once there are this many records in the journal, it is compacted whenever most of them are for jobs which are finished
+const COMPACT_AFTER: usize = 1024;
+
+/// A job which can be written to the journal, see [`Builder::journal`](crate::Builder::journal)
+pub trait PersistentJob: Job + Serialize + DeserializeOwned {}
+
+impl<J: Job + Serialize + DeserializeOwned> PersistentJob for J {}
+
+enum Record<'a> {
+    Enqueue(JobId, &'a str),
+    Merge(JobId, &'a str),
+    Start(JobId),
+    Complete(JobId),
+}
+
+impl fmt::Display for Record<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Record::Enqueue(id, job) => writeln!(f, "E {} {}", id, job),
+            Record::Merge(id, job) => writeln!(f, "M {} {}", id, job),
+            Record::Start(id) => writeln!(f, "S {}", id),
+            Record::Complete(id) => writeln!(f, "C {}", id),
+        }
+    }
+}
+
+/// The jobs which are yet to be completed, serialized, kept in the order they were enqueued
+#[derive(Default)]
+struct Live {
+    /// each job along with its place in the order
+    jobs: BTreeMap<JobId, (u64, String)>,
+    enqueued: u64,
+}
+
+impl Live {
+    /// A job has been enqueued, or changed by being merged into or modified, in which case it keeps its place
+    fn insert(&mut self, id: JobId, job: &str) {
+        match self.jobs.entry(id) {
+            Entry::Occupied(mut entry) => entry.get_mut().1 = job.to_owned(),
+            Entry::Vacant(entry) => {
+                entry.insert((self.enqueued, job.to_owned()));
+                self.enqueued += 1;
+            }
+        }
+    }
+
+    fn remove(&mut self, id: JobId) {
+        self.jobs.remove(&id);
+    }
+
+    fn len(&self) -> usize {
+        self.jobs.len()
+    }
+
+    /// The ids and jobs in the order they were enqueued
+    fn in_order(&self) -> Vec<(JobId, &str)> {
+        let mut jobs: Vec<_> = self.jobs.iter().collect();
+        jobs.sort_by_key(|(_, (order, _))| *order);
+        jobs.into_iter()
+            .map(|(id, (_, job))| (*id, job.as_str()))
+            .collect()
+    }
+}
+
+/// The journal file, shared by all of the jobs which are journaled
+pub(crate) struct JournalFile {
+    path: PathBuf,
+    state: Mutex<JournalState>,
+}
+
+struct JournalState {
+    file: File,
+    /// the jobs which are yet to be completed
+    live: Live,
+    /// the number of records in the file
+    records: usize,
+    /// compaction is held off until the unfinished jobs have been restored, so that they aren't lost
+    restored: bool,
+}
+
+impl JournalFile {
+    /// Open the journal at `path`, returning it along with the ids and serialized jobs which were never completed, in the order they were enqueued
+    fn open(path: PathBuf) -> io::Result<(Self, Vec<(JobId, String)>)> {
+        let unfinished = match fs::read_to_string(&path) {
+            Ok(contents) => replay(&contents),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e),
+        };
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let journal = Self {
+            path,
+            state: Mutex::new(JournalState {
+                file,
+                live: Live::default(),
+                records: 0,
+                restored: false,
+            }),
+        };
+        Ok((journal, unfinished))
+    }
+
+    fn append(&self, record: Record<'_>) {
+        let mut state = self.state.lock();
+        match record {
+            Record::Enqueue(id, job) | Record::Merge(id, job) => state.live.insert(id, job),
+            Record::Start(_) => {}
+            Record::Complete(id) => state.live.remove(id),
+        }
+        if let Err(e) = state
+            .file
+            .write_all(record.to_string().as_bytes())
+            .and_then(|_| state.file.sync_data())
+        {
+            log::warn!("Failed to write to job journal {:?}: {}", self.path, e);
+        }
+        state.records += 1;
+        if state.restored && state.records > COMPACT_AFTER && state.records / 2 > state.live.len() {
+            self.compact_locked(&mut state);
+        }
+    }
+
+    /// Rewrite the journal with just the jobs which are yet to be completed, after which it is compacted automatically
+    pub fn compact(&self) {
+        let mut state = self.state.lock();
+        state.restored = true;
+        self.compact_locked(&mut state);
+    }
+
+    fn compact_locked(&self, state: &mut JournalState) {
+        let mut contents = String::new();
+        for (id, job) in state.live.in_order() {
+            contents.push_str(&Record::Enqueue(id, job).to_string());
+        }
+        // write then rename, so that the journal is never left half-written
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        let result = File::create(&tmp)
+            .and_then(|mut file| {
+                file.write_all(contents.as_bytes())?;
+                file.sync_data()
+            })
+            .and_then(|_| fs::rename(&tmp, &self.path))
+            .and_then(|_| OpenOptions::new().append(true).open(&self.path));
+        match result {
+            Ok(file) => {
+                state.file = file;
+                state.records = state.live.len();
+            }
+            Err(e) => log::warn!("Failed to compact job journal {:?}: {}", self.path, e),
+        }
+    }
+}
+
+/// The ids and serialized jobs from the journal `contents` which were never completed, in the order they were enqueued. The ids given out from now on are greater than any in the journal
+fn replay(contents: &str) -> Vec<(JobId, String)> {
+    let mut live = Live::default();
+    for line in contents.split_inclusive('\n') {
+        // the last line may not have been completely written when the process stopped
+        let line = match line.strip_suffix('\n') {
+            Some(line) => line,
+            None => {
+                log::warn!("Skipping incomplete job journal record {:?}", line);
+                continue;
+            }
+        };
+        let mut parts = line.splitn(3, ' ');
+        let kind = parts.next();
+        let id = parts
+            .next()
+            .and_then(|id| id.strip_prefix('#'))
+            .and_then(|id| id.parse::<u64>().ok())
+            .map(JobId::restored);
+        match (kind, id, parts.next()) {
+            (Some("E"), Some(id), Some(job)) | (Some("M"), Some(id), Some(job)) => {
+                live.insert(id, job)
+            }
+            (Some("S"), Some(_), None) => {}
+            (Some("C"), Some(id), None) => live.remove(id),
+            _ => log::warn!("Skipping invalid job journal record {:?}", line),
+        }
+    }
+    live.in_order()
+        .into_iter()
+        .map(|(id, job)| (id, job.to_owned()))
+        .collect()
+}
+
+/// A job's place in the journal
+pub(crate) struct JournalEntry {
+    file: Arc<JournalFile>,
+    id: JobId,
+}
+
+impl JournalEntry {
+    /// Record that the job has started, it is recorded as completed once the returned guard is dropped, so this includes if it panics
+    pub fn start(self) -> Started {
+        self.file.append(Record::Start(self.id));
+        Started(Some(self))
+    }
+
+    /// Record that the job was completed, or that it was removed and won't be executed
+    pub fn finish(&self) {
+        self.file.append(Record::Complete(self.id));
+    }
+}
+
+/// Records that the job is completed when dropped
+pub(crate) struct Started(Option<JournalEntry>);
+
+impl Started {
+    /// The job is going to be retried, so it stays in the journal rather than being completed
+    pub fn retry(mut self) -> JournalEntry {
+        self.0.take().expect("the entry is only taken once")
+    }
+}
+
+impl Drop for Started {
+    fn drop(&mut self) {
+        if let Some(entry) = &self.0 {
+            entry.finish();
+        }
+    }
+}
+
+/// The jobs from the journal which were never completed, with their ids
+pub(crate) type Unfinished<J> = Vec<(JobId, J)>;
+
+/// The journal, along with how to serialize the jobs in it
+pub(crate) struct Journal<J> {
+    file: Arc<JournalFile>,
+    encode: fn(&J) -> serde_json::Result<String>,
+}
+
+impl<J: PersistentJob> Journal<J> {
+    /// Open the journal at `path`, returning it along with the jobs which were never completed and their ids, in the order they were enqueued
+    pub fn open(path: PathBuf) -> io::Result<(Self, Unfinished<J>)> {
+        let (file, unfinished) = JournalFile::open(path)?;
+        let jobs = unfinished
+            .iter()
+            .filter_map(|(id, job)| match serde_json::from_str(job) {
+                Ok(job) => Some((*id, job)),
+                Err(e) => {
+                    log::warn!("Skipping job from journal which can't be read: {}", e);
+                    None
+                }
+            })
+            .collect();
+        let journal = Self {
+            file: Arc::new(file),
+            encode: |job| serde_json::to_string(job),
+        };
+        Ok((journal, jobs))
+    }
+}
+
+impl<J> Journal<J> {
+    /// Record that `job` has been sent to the runner
+    pub fn enqueue(&self, job: &mut QueuedJob<J>) {
+        if let Some(encoded) = self.encode(&job.job) {
+            self.file.append(Record::Enqueue(job.id, &encoded));
+            job.journal = Some(JournalEntry {
+                file: self.file.clone(),
+                id: job.id,
+            });
+        }
+    }
+
+    /// Record that another job has been merged into `job`, if `job` wasn't already journaled, it is now, as it is doing the work of a job which was
+    fn merged(&self, job: &mut QueuedJob<J>) {
+        if job.journal.is_none() {
+            self.enqueue(job);
+        } else if let Some(encoded) = self.encode(&job.job) {
+            self.file.append(Record::Merge(job.id, &encoded));
+        }
+    }
+
+    /// Merge `job` into `into` like [`QueuedJob::merge`], recording the merge before the merged job is finished, so that its work is never missing from the journal
+    pub fn merge(
+        &self,
+        mut job: QueuedJob<J>,
+        into: &mut QueuedJob<J>,
+        merge_fn: fn(J, &mut J) -> MergeResult<J>,
+    ) -> MergeResult<QueuedJob<J>>
+    where
+        J: Job,
+    {
+        let entry = job.journal.take();
+        let journaled = entry.is_some() || into.journal.is_some();
+        match job.merge(into, merge_fn) {
+            MergeResult::Success => {
+                if journaled {
+                    self.merged(into);
+                }
+                if let Some(entry) = entry {
+                    entry.finish();
+                }
+                MergeResult::Success
+            }
+            MergeResult::NotMerged(mut job) => {
+                job.journal = entry;
+                MergeResult::NotMerged(job)
+            }
+        }
+    }
+
+    /// Record that a journaled `job` has been modified
+    pub fn modified(&self, job: &QueuedJob<J>) {
+        if job.journal.is_some() {
+            if let Some(encoded) = self.encode(&job.job) {
+                self.file.append(Record::Enqueue(job.id, &encoded));
+            }
+        }
+    }
+
+    /// Rewrite the journal with just the jobs which are yet to be completed
+    pub fn compact(&self) {
+        self.file.compact();
+    }
+
+    fn encode(&self, job: &J) -> Option<String> {
+        match (self.encode)(job) {
+            Ok(encoded) => Some(encoded),
+            Err(e) => {
+                log::warn!("Failed to write job to journal: {}", e);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn replay_unfinished() {
+        let contents = "E #1 \"a\"\nE #2 \"b\"\nS #1\nE #10 \"c\"\nM #2 \"bd\"\nE #3 \"d\"\nC #3\nC #1\nE #4 \"tru";
+        let unfinished: Vec<_> = replay(contents)
+            .into_iter()
+            .map(|(id, job)| (id.to_string(), job))
+            .collect();
+        assert_eq!(
+            unfinished,
+            vec![
+                ("#2".into(), "\"bd\"".into()),
+                ("#10".into(), "\"c\"".into())
+            ]
+        );
+    }
+
+    // retried and delayed jobs can be enqueued after jobs with greater ids, modified and merged jobs keep their place
+    #[test]
+    fn replay_in_enqueue_order() {
+        let contents = "E #7 \"a\"\nE #5 \"b\"\nE #6 \"c\"\nM #7 \"ad\"\nE #5 \"e\"\n";
+        let unfinished: Vec<_> = replay(contents)
+            .into_iter()
+            .map(|(id, job)| (id.to_string(), job))
+            .collect();
+        assert_eq!(
+            unfinished,
+            vec![
+                ("#7".into(), "\"ad\"".into()),
+                ("#5".into(), "\"e\"".into()),
+                ("#6".into(), "\"c\"".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn ids_continue_after_replay() {
+        let contents = "E #1000000 \"a\"\nC #1000000\nE #999999 \"b\"\n";
+        assert_eq!(replay(contents)[0].0.to_string(), "#999999");
+        // the id of a job enqueued now can't clash with the one which is restored, or any in the journal
+        let next: u64 = JobId::next().to_string()[1..].parse().unwrap();
+        assert!(next > 1000000);
+    }
+
+    #[test]
+    fn compaction() {
+        let path = std::env::temp_dir().join(format!("gaffer-journal-{}.jobs", std::process::id()));
+        // the temporary file written whilst compacting doesn't replace the extension, so this is left alone
+        let other = path.with_extension("tmp");
+        fs::write(&other, "other").unwrap();
+        let _ = fs::remove_file(&path);
+        let (file, unfinished) = JournalFile::open(path.clone()).unwrap();
+        assert!(unfinished.is_empty());
+        let (a, b, c) = (JobId::next(), JobId::next(), JobId::next());
+        file.append(Record::Enqueue(c, "\"c\""));
+        file.append(Record::Enqueue(a, "\"a\""));
+        file.append(Record::Enqueue(b, "\"b\""));
+        file.append(Record::Start(a));
+        file.append(Record::Complete(a));
+        file.compact();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            format!("E {} \"c\"\nE {} \"b\"\n", c, b)
+        );
+        file.append(Record::Merge(b, "\"bd\""));
+        drop(file);
+        let (_file, unfinished) = JournalFile::open(path.clone()).unwrap();
+        assert_eq!(
+            unfinished,
+            vec![(c, "\"c\"".to_owned()), (b, "\"bd\"".to_owned())]
+        );
+        assert_eq!(fs::read_to_string(&other).unwrap(), "other");
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&other).unwrap();
+    }
+}
@@ -5,17 +5,23 @@
 //! __Features__
 //!
 //! * Recurring jobs: jobs which will be re-enqueued at some interval
+//! * Delayed jobs: jobs which will be enqueued once some time has passed, see [`JobRunner::send_after`]
+//! * Retries: jobs which fail can be retried with a backoff, see [`FallibleJob`] and [`Builder::retry_policy`]
+//! * Dead letters: jobs which fail permanently or panic can be kept to be inspected and requeued, see [`Builder::dead_letters`]
+//! * Job deadlines: jobs which are still queued once their deadline has passed expire rather than being executed, see [`Job::deadline`]
 //! * Job queue: send jobs from various threads using the cloneable [`JobRunner`]
+//! * Job tracking: check on the status of a job, or cancel it, using the [`JobHandle`] from [`JobRunner::send_tracked`]
 //! * Future Jobs: (Optionally) create `Future`s to get results from the jobs
 //! * Job prioritisation: provide a priority for jobs and all the jobs will be executed in that order
 //! * Job merging: merge identical / similar jobs in the queue to reduce workload
 //! * Parallel execution: run jobs on multiple threads and lock jobs which should be run exclusively, they remain in the queue and don't occupy other resources
 //! * Concurrent exclusion: key-based locking to avoid jobs running concurrently which shouldn't
 //! * Priority throttling: in order to have idle threads ready to pick up higher-priority jobs, throttle lower priority jobs by restricting them to a lower number of threads
+//! * Job journal: (Optionally, with the `journal` feature) keep a journal of the queued jobs on disk, so that the jobs which never completed are restored after a crash or restart, see `Builder::journal`
 //!
 //! __Limitations__
 //!
-//! * some of the tests are very dependent on timing and will fail if run slowly
+//! * some of the tests are very dependent on timing and will fail if run slowly, recurring jobs can be tested deterministically by building the runner with a [`TestClock`], see [`Builder::clock`], and scheduling decisions can be tested without any threads using [`Builder::build_manual`]
 //!
 //! ## Example
 //!
@@ -31,6 +37,14 @@
 //!
 //! You need to call [`Builder::set_recurring`] and you need to implement [`RecurrableJob`].
 //!
+//! By default the interval is measured from when a matching job was last enqueued, [`Builder::set_recurring_anchored`] can instead measure it from when a matching job last completed, or recur at a fixed rate, see [`RecurrenceAnchor`]. With [`Builder::set_recurring_policy`], a job can also be made a singleton, so that it isn't enqueued whilst a matching job is queued or running, and a fixed rate job can catch up on the runs it missed, see [`MissedRunPolicy`]. The policy can also add random [`Jitter`] to each recurrence, set when the job first runs, and persist when the job last ran to a [`StateStore`] such as [`FileStateStore`], so that it keeps to its schedule across restarts.
+//!
+//! Jobs can also be enqueued at particular times of day, using a cron expression with [`Builder::set_recurring_cron`].
+//!
+//! For other policies, implement [`RecurringJob`] and add it with [`Builder::add_recurring`], this doesn't require the job to implement [`RecurrableJob`].
+//!
+//! Jobs which can't be cloned can still recur with [`Builder::set_recurring_with`], which builds a new job each time it recurs.
+//!
 //! ```
 //! use gaffer::{Job, JobRunner, NoExclusion, RecurrableJob};
 //! use std::time::Duration;
@@ -196,7 +210,7 @@
 //!
 //! ### Parallel execution
 //!
-//! Jobs can be run over multiple threads, just provide the number of threads to [`Builder::build`]
+//! Jobs can be run over multiple threads, just provide the number of threads to [`Builder::build`]. The number of threads can be changed later with [`JobRunner::set_threads`], or scaled with the load using [`Builder::autoscale`]
 //!
 //! ```
 //! fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -393,20 +407,60 @@ use std::{
     sync::Arc,
     time::{Duration, Instant},
 };
+#[cfg(feature = "journal")]
+use std::{io, path::PathBuf};
 
-use runner::ConcurrencyLimitFn;
-pub use source::RecurrableJob;
-use source::{IntervalRecurringJob, RecurringJob, SourceManager};
+pub use clock::{Clock, SystemClock, TestClock};
+pub use dead_letter::{DeadLetter, DeadLetterReason, DeadLetterSink, InMemoryDeadLetters};
+use handle::QueuedJob;
+pub use handle::{JobHandle, JobId, JobStatus};
+#[cfg(feature = "journal")]
+pub use journal::PersistentJob;
+#[cfg(feature = "journal")]
+use journal::{Journal, Unfinished};
+pub use manual::ManualRunner;
+use retry::Retry;
+pub use retry::{FallibleJob, JobOutcome, RetryPolicy};
+use runner::{Autoscale, ConcurrencyLimitFn, Execution, ExpiredFn, Expiry, PanicHook, Pool};
+pub use runner::{JobPanic, Metrics, ShutdownMode, WorkerStatus};
+pub use source::{
+    cron::{CronError, CronRecurringJob, CronSchedule},
+    schedule::RecurringHandle,
+    store::{FileStateStore, StateStore},
+};
+use source::{
+    delayed::Delayed,
+    schedule::Schedules,
+    util::{prioritized_mpsc, MergeFn},
+    FactoryRecurringJob, IntervalRecurringJob, Recurrence, SourceManager,
+};
+pub use source::{
+    Jitter, MissedRunPolicy, RecurrableJob, RecurrenceAnchor, RecurringJob, RecurringPolicy,
+};
 
+mod clock;
+mod dead_letter;
 pub mod future;
+mod handle;
+#[cfg(feature = "journal")]
+mod journal;
+mod manual;
+mod retry;
 mod runner;
 mod source;
 
-/// Top level structure of the crate. Currently, recurring jobs would keep being scheduled once this is dropped, but that will probably change.
+/// Top level structure of the crate. Once this and all of its clones are dropped, recurring jobs stop being scheduled and the worker threads exit, see [`Builder::drop_mode`]. Use [`JobRunner::shutdown`] to stop it and wait for the workers.
 ///
 /// See crate level docs
-pub struct JobRunner<J> {
-    sender: crossbeam_channel::Sender<J>,
+pub struct JobRunner<J: Job + 'static> {
+    sender: prioritized_mpsc::Sender<QueuedJob<J>>,
+    pool: Arc<Pool<J, Box<dyn RecurringJob<J> + Send>>>,
+    schedules: Arc<Schedules<Box<dyn RecurringJob<J> + Send>>>,
+    delayed: Arc<Delayed<J>>,
+    clock: Arc<dyn Clock>,
+    dead_letters: Option<Arc<dyn DeadLetterSink<J>>>,
+    #[cfg(feature = "journal")]
+    journal: Option<Arc<Journal<J>>>,
 }
 
 impl<J: Job + 'static> JobRunner<J> {
@@ -415,28 +469,284 @@ impl<J: Job + 'static> JobRunner<J> {
         Builder::new()
     }
 
-    /// Send a job to the queue
+    /// Send a job to the queue, fails if the runner is shutting down. If the queue has a capacity (see [`Builder::queue_capacity`]) and it is full, this blocks until there is space
     pub fn send(&self, job: J) -> Result<(), crossbeam_channel::SendError<J>> {
-        self.sender.send(job)
+        if self.pool.is_shutdown() {
+            return Err(crossbeam_channel::SendError(job));
+        }
+        self.sender
+            .send(self.journaled(QueuedJob::new(job)))
+            .map_err(|crossbeam_channel::SendError(job)| {
+                crossbeam_channel::SendError(job.into_inner())
+            })
+    }
+
+    /// Send a job to the queue like [`JobRunner::send`], returning a [`JobHandle`] which can be used to check on the job's progress or cancel it
+    pub fn send_tracked(&self, job: J) -> Result<JobHandle<J>, crossbeam_channel::SendError<J>> {
+        if self.pool.is_shutdown() {
+            return Err(crossbeam_channel::SendError(job));
+        }
+        let (job, tracker) = QueuedJob::tracked(job);
+        let handle = JobHandle::new(&job, tracker, Arc::downgrade(&self.sender.queue()));
+        self.sender
+            .send(self.journaled(job))
+            .map_err(|crossbeam_channel::SendError(job)| {
+                crossbeam_channel::SendError(job.into_inner())
+            })?;
+        Ok(handle)
+    }
+
+    /// Send a job to the queue, if the queue is full (see [`Builder::queue_capacity`]) the job is returned
+    pub fn try_send(&self, job: J) -> Result<(), crossbeam_channel::TrySendError<J>> {
+        if self.pool.is_shutdown() {
+            return Err(crossbeam_channel::TrySendError::Disconnected(job));
+        }
+        self.sender
+            .try_send(self.journaled(QueuedJob::new(job)))
+            .map_err(|err| match err {
+                crossbeam_channel::TrySendError::Full(job) => {
+                    crossbeam_channel::TrySendError::Full(job.into_inner())
+                }
+                crossbeam_channel::TrySendError::Disconnected(job) => {
+                    crossbeam_channel::TrySendError::Disconnected(job.into_inner())
+                }
+            })
+    }
+
+    /// Send a job to the queue, if the queue is full (see [`Builder::queue_capacity`]) this blocks until there is space, or the `timeout` passes and the job is returned
+    pub fn send_timeout(
+        &self,
+        job: J,
+        timeout: Duration,
+    ) -> Result<(), crossbeam_channel::SendTimeoutError<J>> {
+        if self.pool.is_shutdown() {
+            return Err(crossbeam_channel::SendTimeoutError::Disconnected(job));
+        }
+        self.sender
+            .send_timeout(self.journaled(QueuedJob::new(job)), timeout)
+            .map_err(|err| match err {
+                crossbeam_channel::SendTimeoutError::Timeout(job) => {
+                    crossbeam_channel::SendTimeoutError::Timeout(job.into_inner())
+                }
+                crossbeam_channel::SendTimeoutError::Disconnected(job) => {
+                    crossbeam_channel::SendTimeoutError::Disconnected(job.into_inner())
+                }
+            })
+    }
+
+    /// Send a job to be enqueued once `at` has passed, according to the runner's clock (see [`Builder::clock`]). Until then it waits outside the queue, so it isn't merged, doesn't take up any of the queue's capacity and isn't affected by [`JobRunner::retain`] or [`JobRunner::modify_queued`]. Once it is due, it is enqueued like any other job. Fails if the runner is shutting down
+    ///
+    /// Delayed jobs which are yet to be enqueued when the runner shuts down are returned by [`JobRunner::shutdown`]. If the runner has a journal, a delayed job is restored without its delay
+    pub fn send_at(&self, at: Instant, job: J) -> Result<(), crossbeam_channel::SendError<J>> {
+        if self.pool.is_shutdown() {
+            return Err(crossbeam_channel::SendError(job));
+        }
+        self.delayed.add(at, self.journaled(QueuedJob::new(job)));
+        Ok(())
+    }
+
+    /// Send a job to be enqueued once `delay` has passed, see [`JobRunner::send_at`]
+    pub fn send_after(
+        &self,
+        delay: Duration,
+        job: J,
+    ) -> Result<(), crossbeam_channel::SendError<J>> {
+        self.send_at(self.clock.now() + delay, job)
+    }
+
+    /// Send the jobs from the dead letters for which `predicate` returns `true` back to the queue (see [`Builder::dead_letters`]), as new jobs with new ids. Their attempts are reset, so each of them is tried up to [`RetryPolicy::max_attempts`] times again, rather than only once more. Dead letters without a job, because it was consumed by executing it, can't be requeued so they are left in the sink, as are those which can't be sent as the runner is shutting down. Returns how many jobs were requeued
+    pub fn requeue_dead_letters(&self, predicate: impl FnMut(&DeadLetter<J>) -> bool) -> usize {
+        if let Some(dead_letters) = &self.dead_letters {
+            dead_letter::requeue(&**dead_letters, predicate, |job| {
+                self.send(job)
+                    .map_err(|crossbeam_channel::SendError(job)| job)
+            })
+        } else {
+            0
+        }
+    }
+
+    /// Remove the jobs waiting to be executed for which `f` returns `false`, those jobs are dropped without being executed
+    pub fn retain(&self, mut f: impl FnMut(&J) -> bool) {
+        self.sender.retain(|queued| {
+            let keep = f(&queued.job);
+            if !keep {
+                queued.mark_removed();
+            }
+            keep
+        });
+    }
+
+    /// Apply `f` to each of the jobs waiting to be executed, after which they are reordered according to their new [`Job::priority`] and merged if they can be (see [`Builder::enable_merge`])
+    pub fn modify_queued(&self, mut f: impl FnMut(&mut J)) {
+        self.sender.modify(|queued| {
+            f(&mut queued.job);
+            #[cfg(feature = "journal")]
+            if let Some(journal) = &self.journal {
+                journal.modified(queued);
+            }
+        });
+    }
+
+    /// Record the job in the journal, if the runner has one
+    #[cfg_attr(not(feature = "journal"), allow(unused_mut))]
+    fn journaled(&self, mut job: QueuedJob<J>) -> QueuedJob<J> {
+        #[cfg(feature = "journal")]
+        if let Some(journal) = &self.journal {
+            journal.enqueue(&mut job);
+        }
+        job
+    }
+
+    /// Handles on each of the recurring jobs, both those set on the [`Builder`] and those added with [`JobRunner::add_recurring`] or [`JobRunner::add_recurring_job`], which can be used to control them whilst running. They are in the order the jobs were added, starting with those set on the [`Builder`], and leaving out any which have been cancelled
+    pub fn recurring_handles(&self) -> Vec<RecurringHandle> {
+        self.schedules.handles()
+    }
+
+    /// Add a recurring job with any policy for when it recurs whilst running, see [`Builder::add_recurring`]. The returned handle can be used to control it
+    pub fn add_recurring_job(
+        &self,
+        recurring: impl RecurringJob<J> + Send + 'static,
+    ) -> RecurringHandle {
+        self.schedules.add(Box::new(recurring))
+    }
+
+    /// Change the number of worker threads to `thread_num`, this affects all the clones of this `JobRunner`. New threads start straight away, if there are fewer threads needed, idle threads exit straight away and busy threads exit once they have finished their current job. Does nothing if the runner is shutting down.
+    ///
+    /// Panics if `thread_num` is 0
+    pub fn set_threads(&self, thread_num: usize) {
+        self.pool.set_threads(thread_num);
+    }
+
+    /// Get the jobs waiting to be executed, grouped by priority with the highest priority first, along with what each of the workers is doing
+    pub fn snapshot(&self) -> Snapshot<J>
+    where
+        J: Clone,
+    {
+        Snapshot {
+            queue: queue_snapshot(&self.sender),
+            workers: self.pool.workers(),
+        }
+    }
+
+    /// Get counts of what has happened to the jobs since the runner was built, this is shared by all the clones of this `JobRunner`
+    pub fn metrics(&self) -> Metrics {
+        self.pool.metrics()
+    }
+
+    /// Stop the runner, this affects all the clones of this `JobRunner`, after which any attempts to send will fail.
+    ///
+    /// Waits up to `timeout` (or indefinitely if `None`) for the worker threads to exit, what they wait for depends on the `mode`, see [`ShutdownMode`], then joins them. If the timeout passes, no more jobs will be started. With [`ShutdownMode::Immediate`], or once the timeout has passed, the worker threads aren't joined: any which are still executing a job are left detached and exit once it has finished.
+    ///
+    /// Returns the jobs which were never executed, in priority order, followed by any delayed jobs (see [`JobRunner::send_at`]) and jobs waiting to be retried (see [`Builder::retry_policy`]) in the order they were due.
+    ///
+    /// Panics if called from a job without a `timeout`, other than with [`ShutdownMode::Immediate`], as the job's worker would wait for itself to finish. The panic is caught like any other in a job, and the runner carries on.
+    pub fn shutdown(self, mode: ShutdownMode, timeout: Option<Duration>) -> Vec<J> {
+        self.pool.shutdown(mode, timeout)
     }
 }
 
-impl<J> Clone for JobRunner<J> {
+impl<J: Job + RecurrableJob + 'static> JobRunner<J> {
+    /// Add a recurring job whilst running, the job will be enqueued every time `interval` passes since the `last_enqueue` of a matching job, see [`Builder::set_recurring`]. The returned handle can be used to control it
+    pub fn add_recurring(
+        &self,
+        interval: Duration,
+        last_enqueue: Instant,
+        job: J,
+    ) -> RecurringHandle {
+        self.schedules.add(Box::new(IntervalRecurringJob::new(
+            interval,
+            last_enqueue,
+            RecurringPolicy::default(),
+            job,
+            self.clock.clone(),
+        )))
+    }
+}
+
+impl<J: Job + 'static> Clone for JobRunner<J> {
     fn clone(&self) -> Self {
         Self {
             sender: self.sender.clone(),
+            pool: self.pool.clone(),
+            schedules: self.schedules.clone(),
+            delayed: self.delayed.clone(),
+            clock: self.clock.clone(),
+            dead_letters: self.dead_letters.clone(),
+            #[cfg(feature = "journal")]
+            journal: self.journal.clone(),
         }
     }
 }
 
+/// The jobs waiting to be executed, grouped by priority with the highest priority first
+fn queue_snapshot<J: Job + Clone>(
+    sender: &prioritized_mpsc::Sender<QueuedJob<J>>,
+) -> Vec<(J::Priority, Vec<J>)> {
+    sender.inspect(|queue, pending| {
+        let mut groups: Vec<(J::Priority, Vec<J>)> = queue
+            .buckets()
+            .map(|(priority, jobs)| {
+                (
+                    priority,
+                    jobs.iter().map(|queued| queued.job.clone()).collect(),
+                )
+            })
+            .collect();
+        // jobs which are yet to be received will be enqueued after those in the queue
+        for queued in pending {
+            let priority = queued.job.priority();
+            match groups.binary_search_by(|(p, _)| priority.cmp(p)) {
+                Ok(idx) => groups[idx].1.push(queued.job.clone()),
+                Err(idx) => groups.insert(idx, (priority, vec![queued.job.clone()])),
+            }
+        }
+        groups
+    })
+}
+
+/// State of a [`JobRunner`] at some moment, see [`JobRunner::snapshot`]
+pub struct Snapshot<J: Job> {
+    /// The jobs waiting in the queue, grouped by priority with the highest priority first, each group is in the order the jobs would be executed
+    pub queue: Vec<(J::Priority, Vec<J>)>,
+    /// What each of the workers is doing, in order of their index
+    pub workers: Vec<WorkerStatus<J::Exclusion>>,
+}
+
+impl<J: Job + fmt::Debug> fmt::Debug for Snapshot<J>
+where
+    J::Priority: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Snapshot")
+            .field("queue", &self.queue)
+            .field("workers", &self.workers)
+            .finish()
+    }
+}
+
 /// Builder of [`JobRunner`]
 pub struct Builder<J: Job + 'static> {
     concurrency_limit: Box<ConcurrencyLimitFn<J>>,
-    recurring: Vec<Box<dyn RecurringJob<J> + Send>>,
+    recurring: Vec<Box<RecurringFn<J>>>,
     /// optional function to allow merging of jobs
     merge_fn: Option<fn(J, &mut J) -> MergeResult<J>>,
+    drop_mode: ShutdownMode,
+    queue_capacity: Option<usize>,
+    autoscale: Option<Autoscale>,
+    clock: Arc<dyn Clock>,
+    on_expired: Option<Box<ExpiredFn<J>>>,
+    on_panic: Option<PanicHook<J>>,
+    retry: Option<Retry<J>>,
+    dead_letters: Option<Arc<dyn DeadLetterSink<J>>>,
+    /// the journal, along with the jobs from it which are yet to be restored
+    #[cfg(feature = "journal")]
+    journal: Option<(Journal<J>, Unfinished<J>)>,
 }
 
+/// Creates a recurring job once the clock it is scheduled by is known, when the runner is built
+type RecurringFn<J> = dyn FnOnce(&Arc<dyn Clock>) -> Box<dyn RecurringJob<J> + Send> + Send;
+
 impl<J: Job + Send + 'static> Builder<J> {
     /// Start building a [`JobRunner`]
     fn new() -> Self {
@@ -444,6 +754,16 @@ impl<J: Job + Send + 'static> Builder<J> {
             concurrency_limit: Box::new(|_: <J as Prioritised>::Priority| None as Option<u8>),
             recurring: vec![],
             merge_fn: None,
+            drop_mode: ShutdownMode::Drain,
+            queue_capacity: None,
+            autoscale: None,
+            clock: Arc::new(SystemClock),
+            on_expired: None,
+            on_panic: None,
+            retry: None,
+            dead_letters: None,
+            #[cfg(feature = "journal")]
+            journal: None,
         }
     }
 
@@ -456,13 +776,60 @@ impl<J: Job + Send + 'static> Builder<J> {
 
 impl<J: Job + Send + RecurrableJob + 'static> Builder<J> {
     /// Set a job as recurring, the job will be enqueued every time `interval` passes since the `last_enqueue` of a matching job
-    pub fn set_recurring(mut self, interval: Duration, last_enqueue: Instant, job: J) -> Self {
-        self.recurring.push(Box::new(IntervalRecurringJob {
-            last_enqueue,
-            interval,
-            job,
-        }));
-        self
+    pub fn set_recurring(self, interval: Duration, last_enqueue: Instant, job: J) -> Self {
+        self.add_recurring_clocked(move |clock| {
+            Box::new(IntervalRecurringJob::new(
+                interval,
+                last_enqueue,
+                RecurringPolicy::default(),
+                job,
+                clock.clone(),
+            ))
+        })
+    }
+
+    /// Set a job as recurring, the job will be enqueued every time `interval` passes since the `anchor`, starting from `last_enqueue`. See [`RecurrenceAnchor`] for the options
+    pub fn set_recurring_anchored(
+        self,
+        interval: Duration,
+        last_enqueue: Instant,
+        anchor: RecurrenceAnchor,
+        job: J,
+    ) -> Self {
+        self.add_recurring_clocked(move |clock| {
+            Box::new(IntervalRecurringJob::new(
+                interval,
+                last_enqueue,
+                RecurringPolicy::default().anchor(anchor),
+                job,
+                clock.clone(),
+            ))
+        })
+    }
+
+    /// Set a job as recurring, the job will be enqueued every time `interval` passes according to the `policy`. Unless the policy sets the first run, it is first enqueued once `interval` has passed from when the runner is built
+    pub fn set_recurring_policy(self, interval: Duration, policy: RecurringPolicy, job: J) -> Self {
+        self.add_recurring_clocked(move |clock| {
+            Box::new(IntervalRecurringJob::new(
+                interval,
+                clock.now(),
+                policy,
+                job,
+                clock.clone(),
+            ))
+        })
+    }
+
+    /// Set a job as recurring, the job will be enqueued at each of the times in UTC matching the cron expression `expr`, see [`CronSchedule`] for the format. Fails if `expr` isn't a valid cron expression
+    pub fn set_recurring_cron(self, expr: &str, job: J) -> Result<Self, CronError> {
+        Ok(self.set_recurring_schedule(CronSchedule::parse(expr)?, job))
+    }
+
+    /// Set a job as recurring, the job will be enqueued at each of the times matching the `schedule`, use this rather than [`Builder::set_recurring_cron`] for times which aren't in UTC
+    pub fn set_recurring_schedule(self, schedule: CronSchedule, job: J) -> Self {
+        self.add_recurring_clocked(move |clock| {
+            Box::new(CronRecurringJob::new(schedule, job, clock.clone()))
+        })
     }
 }
 
@@ -482,16 +849,244 @@ impl<J: Job + Send + 'static> Builder<J> {
         self
     }
 
-    /// Build the [`JobRunner`], spawning `thread_num` threads as workers
+    /// Add a recurring job with any policy for when it recurs, see [`RecurringJob`]. For jobs which recur at an interval or according to a cron expression, see [`Builder::set_recurring`] and [`Builder::set_recurring_cron`]
+    ///
+    /// Once the runner is built, the recurring jobs set on the builder can be controlled through [`JobRunner::recurring_handles`], in the order they were set
+    pub fn add_recurring(self, recurring: impl RecurringJob<J> + Send + 'static) -> Self {
+        self.add_recurring_clocked(move |_| Box::new(recurring))
+    }
+
+    /// Add the recurring job created by `recurring` with the clock, once it is known in [`Builder::build`], so that [`Builder::clock`] can be called in any order
+    fn add_recurring_clocked(
+        mut self,
+        recurring: impl FnOnce(&Arc<dyn Clock>) -> Box<dyn RecurringJob<J> + Send> + Send + 'static,
+    ) -> Self {
+        self.recurring.push(Box::new(recurring));
+        self
+    }
+
+    /// Set a job as recurring without needing it to implement [`RecurrableJob`], each time it recurs a new job is built with `factory`. The job will be enqueued every time `interval` passes since the last time a job for which `matcher` returns `true` was enqueued, or since the runner was built
+    pub fn set_recurring_with(
+        self,
+        interval: Duration,
+        matcher: impl Fn(&J) -> bool + Send + 'static,
+        factory: impl Fn() -> J + Send + 'static,
+    ) -> Self {
+        self.add_recurring_clocked(move |clock| {
+            Box::new(FactoryRecurringJob {
+                recurrence: Recurrence::new(
+                    interval,
+                    clock.now(),
+                    RecurringPolicy::default(),
+                    clock.clone(),
+                ),
+                matcher: Box::new(matcher),
+                factory: Box::new(factory),
+            })
+        })
+    }
+
+    /// Limit the number of jobs waiting to be executed, once the queue is full [`JobRunner::send`] will block and [`JobRunner::try_send`] will return the job. A job which can be merged into one already in the queue doesn't need any more capacity, so it can be sent even when the queue is full, then it is merged straight away and isn't passed to [`RecurringJob::job_enqueued`], as the job it was merged into already has been. Recurring jobs are not limited
+    pub fn queue_capacity(mut self, capacity: usize) -> Self {
+        self.queue_capacity = Some(capacity);
+        self
+    }
+
+    /// How to shut down once the [`JobRunner`] and all of its clones have been dropped. The default, [`ShutdownMode::Drain`], executes whatever is still queued before the workers exit.
+    ///
+    /// Unlike with [`JobRunner::shutdown`], there is nothing to return jobs to, so any jobs which aren't executed are discarded. This always includes delayed jobs (see [`JobRunner::send_at`]) and jobs waiting to be retried (see [`Builder::retry_policy`]), even with [`ShutdownMode::Drain`], as only the queue is drained. Call [`JobRunner::shutdown`] to get them back.
+    pub fn drop_mode(mut self, mode: ShutdownMode) -> Self {
+        self.drop_mode = mode;
+        self
+    }
+
+    /// Use `clock` to tell the time, rather than the [`SystemClock`]. It is used when scheduling recurring and delayed jobs, for deadlines, and for the times reported in [`WorkerStatus`] and dead letters. With a [`TestClock`], recurring jobs are only enqueued once the clock has been advanced past when they're due. Cron schedules and persisted recurring jobs use its [`Clock::system_time`].
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Scale the number of worker threads automatically between `min` and `max`. When there is a job which could be executed, but all the workers are busy, another worker is started. Workers which have been idle for `idle_timeout` exit.
+    ///
+    /// Panics if `min` is 0 or more than `max`
+    pub fn autoscale(mut self, min: usize, max: usize, idle_timeout: Duration) -> Self {
+        assert!(
+            min > 0 && min <= max,
+            "autoscale needs 0 < min <= max, got {}..={}",
+            min,
+            max
+        );
+        self.autoscale = Some(Autoscale {
+            min,
+            max,
+            idle_timeout,
+        });
+        self
+    }
+
+    /// Call `on_expired` with each job which expires before it is started (see [`Job::deadline`]), rather than dropping it. It is called on one of the runner's threads without any of its locks held, so it can send jobs to the runner, but it should be quick. If the queue has a capacity (see [`Builder::queue_capacity`]) it should use [`JobRunner::try_send`], as the queue isn't emptied whilst it waits
+    pub fn on_expired(mut self, on_expired: impl Fn(J) + Send + Sync + 'static) -> Self {
+        self.on_expired = Some(Box::new(on_expired));
+        self
+    }
+
+    /// Call `on_panic` whenever a job panics, with the job's [`Debug`](fmt::Debug) representation and what it panicked with. Either way, the worker carries on with the next job. This is called on the worker which was executing the job
+    pub fn on_panic(mut self, on_panic: impl Fn(JobPanic) + Send + Sync + 'static) -> Self
+    where
+        J: fmt::Debug,
+    {
+        self.on_panic = Some(PanicHook::new(Box::new(on_panic)));
+        self
+    }
+
+    /// Send jobs which fail permanently or panic to `sink`, from where they can be requeued with [`JobRunner::requeue_dead_letters`]. Use [`InMemoryDeadLetters`] to keep them in memory, or implement [`DeadLetterSink`] to persist them
+    pub fn dead_letters(mut self, sink: impl DeadLetterSink<J> + 'static) -> Self {
+        self.dead_letters = Some(Arc::new(sink));
+        self
+    }
+
+    /// Build the [`JobRunner`], spawning `thread_num` threads as workers. If autoscaling (see [`Builder::autoscale`]), this is the initial number of threads, which is kept within the limits
     pub fn build(self, thread_num: usize) -> JobRunner<J> {
+        let thread_num = if let Some(autoscale) = &self.autoscale {
+            thread_num.clamp(autoscale.min, autoscale.max)
+        } else {
+            thread_num
+        };
+        #[cfg(feature = "journal")]
+        let (journal, restored) = match self.journal {
+            Some((journal, restored)) => (Some(Arc::new(journal)), restored),
+            None => (None, vec![]),
+        };
+        #[cfg(feature = "journal")]
+        let merge_journal = journal.clone();
+        let merge_fn = self.merge_fn.map(|merge_fn| {
+            Box::new(move |job: QueuedJob<J>, into: &mut QueuedJob<J>| {
+                #[cfg(feature = "journal")]
+                if let Some(journal) = &merge_journal {
+                    return journal.merge(job, into, merge_fn);
+                }
+                job.merge(into, merge_fn)
+            }) as Box<MergeFn<QueuedJob<J>>>
+        });
+        let clock = self.clock.clone();
         let (sender, sources) =
             SourceManager::<J, Box<dyn RecurringJob<J> + Send>>::new_with_recurring(
-                self.recurring,
-                self.merge_fn,
+                self.recurring
+                    .into_iter()
+                    .map(|recurring| recurring(&clock))
+                    .collect(),
+                merge_fn,
+                self.queue_capacity,
+                self.clock.clone(),
             );
+        let schedules = sources.schedules();
+        let delayed = sources.delayed();
+        let retry = self
+            .retry
+            .map(|retry| retry.retrier(delayed.clone(), clock));
+        #[cfg(feature = "journal")]
+        let mut overflow = vec![];
+        #[cfg(feature = "journal")]
+        if let Some(journal) = &journal {
+            for (id, job) in restored {
+                // keeping the id means the job's new records follow on from its old ones, rather than duplicating it
+                let mut job = QueuedJob::with_id(job, id);
+                journal.enqueue(&mut job);
+                // enqueued before the workers start so that they're executed in priority order, those which don't fit in the queue are sent once the workers have started
+                if let Err(crossbeam_channel::TrySendError::Full(job)) = sender.try_send(job) {
+                    overflow.push(job);
+                }
+            }
+        }
         let jobs = Arc::new(Mutex::new(sources));
-        let _threads = runner::spawn(thread_num, jobs, self.concurrency_limit);
-        JobRunner { sender }
+        let pool = runner::spawn(
+            thread_num,
+            jobs,
+            self.concurrency_limit,
+            self.drop_mode,
+            self.autoscale,
+            Execution {
+                clock: self.clock.clone(),
+                expiry: Expiry::new(self.clock.clone(), self.on_expired),
+                on_panic: self.on_panic,
+                retry,
+                dead_letters: self.dead_letters.clone(),
+            },
+        );
+        let runner = JobRunner {
+            sender,
+            pool: Arc::new(pool),
+            schedules,
+            delayed,
+            clock: self.clock,
+            dead_letters: self.dead_letters,
+            #[cfg(feature = "journal")]
+            journal,
+        };
+        #[cfg(feature = "journal")]
+        if let Some(journal) = &runner.journal {
+            for job in overflow {
+                let _ = runner.sender.send(job);
+            }
+            // the restored jobs have been journaled again, so the old records can go
+            journal.compact();
+        }
+        runner
+    }
+
+    /// Build a [`ManualRunner`] with `slots` worker slots and no threads, which only executes jobs when it is stepped. The concurrency limits, merging, queue capacity, clock, expiry and panic callbacks, retry policy, dead letter sink and recurring jobs are all used, the drop mode, autoscaling and journal aren't
+    ///
+    /// Panics if `slots` is 0
+    pub fn build_manual(self, slots: usize) -> ManualRunner<J> {
+        let merge_fn = self.merge_fn.map(|merge_fn| {
+            Box::new(move |job: QueuedJob<J>, into: &mut QueuedJob<J>| job.merge(into, merge_fn))
+                as Box<MergeFn<QueuedJob<J>>>
+        });
+        let clock = self.clock.clone();
+        let (sender, jobs) =
+            SourceManager::<J, Box<dyn RecurringJob<J> + Send>>::new_with_recurring(
+                self.recurring
+                    .into_iter()
+                    .map(|recurring| recurring(&clock))
+                    .collect(),
+                merge_fn,
+                self.queue_capacity,
+                self.clock.clone(),
+            );
+        let retry = self.retry.map(|retry| retry.retrier(jobs.delayed(), clock));
+        ManualRunner::new(
+            sender,
+            jobs,
+            slots,
+            self.concurrency_limit,
+            Execution {
+                clock: self.clock.clone(),
+                expiry: Expiry::new(self.clock.clone(), self.on_expired),
+                on_panic: self.on_panic,
+                retry,
+                dead_letters: self.dead_letters,
+            },
+            self.clock,
+        )
+    }
+}
+
+impl<J: FallibleJob + Send + 'static> Builder<J> {
+    /// Execute jobs with [`FallibleJob::try_execute`], retrying those which ask to be according to `policy`. Jobs which are to be retried are enqueued again once their delay has passed, like [`JobRunner::send_after`], where they can be merged. A job which has been tracked keeps its [`JobHandle`], it is [`JobStatus::Queued`] until it is retried
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(Retry::new(policy));
+        self
+    }
+}
+
+#[cfg(feature = "journal")]
+impl<J: PersistentJob + 'static> Builder<J> {
+    /// Keep a journal of the jobs sent to the runner in the file at `path`. Jobs which were never completed, because the process crashed or the runner was shut down, are restored when a runner is next built with the same journal. Jobs which had started but not completed are restored too, so a job could be executed more than once. Recurring jobs aren't journaled, unless a journaled job is merged into one. Each record is synced to disk as it is written, so once a job has been sent it survives a crash.
+    ///
+    /// Fails if the journal can't be read
+    pub fn journal(mut self, path: impl Into<PathBuf>) -> io::Result<Self> {
+        self.journal = Some(Journal::open(path.into())?);
+        Ok(self)
     }
 }
 
@@ -509,6 +1104,11 @@ pub trait Job: Send {
     /// Get the priority of this thing
     fn priority(&self) -> Self::Priority;
 
+    /// The latest instant by which the job should have started, according to the runner's clock (see [`Builder::clock`]). If it is still waiting once this has passed, it expires: it is removed from the queue and passed to the callback set with [`Builder::on_expired`] rather than being executed. By default jobs don't expire
+    fn deadline(&self) -> Option<Instant> {
+        None
+    }
+
     /// Execute and consume the job
     fn execute(self);
 }
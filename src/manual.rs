@@ -0,0 +1,357 @@
+//! Runner without any threads, which executes jobs on the calling thread one step at a time, see [`ManualRunner`]
+
+use std::{collections::VecDeque, sync::Arc, time::Instant};
+
+use crate::{
+    clock::Clock,
+    dead_letter,
+    handle::QueuedJob,
+    queue_snapshot,
+    runner::{ConcurrencyLimitFn, Execution},
+    source::{
+        schedule::RecurringHandle,
+        util::{may_be_taken::SkipIterator, prioritized_mpsc},
+        RecurringJob, SourceManager,
+    },
+    DeadLetter, Job, JobHandle, Metrics, Snapshot, WorkerStatus,
+};
+
+/// Runner with no background threads, which is driven by calling [`ManualRunner::step`], so that the scheduling of jobs can be tested deterministically. See [`Builder::build_manual`](crate::Builder::build_manual)
+///
+/// Rather than worker threads, it has a number of worker slots. Each step, queued jobs are assigned to the free slots in the same way as the supervisor assigns them to available workers, following the concurrency limits and exclusions of the jobs in the other slots. Then the job which was assigned first is executed on the calling thread, freeing up its slot. Jobs which have been assigned but not yet executed act as if they are running.
+///
+/// ```
+/// use gaffer::{ExclusionOption, Job, JobRunner};
+///
+/// #[derive(Clone)]
+/// struct ExcludedJob(char, u8);
+///
+/// impl Job for ExcludedJob {
+///     type Exclusion = ExclusionOption<u8>;
+///
+///     fn exclusion(&self) -> Self::Exclusion {
+///         self.1.into()
+///     }
+///
+///     type Priority = ();
+///
+///     fn priority(&self) -> Self::Priority {}
+///
+///     fn execute(self) {
+///         println!("{}", self.0);
+///     }
+/// }
+///
+/// let mut runner = JobRunner::builder().build_manual(2);
+/// runner.send(ExcludedJob('a', 1)).unwrap();
+/// runner.send(ExcludedJob('b', 1)).unwrap();
+/// runner.send(ExcludedJob('c', 2)).unwrap();
+/// assert!(runner.step()); // a & c are assigned, b waits behind a as they share exclusion key 1, then a is executed
+/// assert_eq!(runner.snapshot().queue.len(), 1); // b is still queued, it is assigned once a has finished
+/// assert_eq!(runner.run_until_idle(), 2); // c then b
+/// ```
+pub struct ManualRunner<J: Job + 'static> {
+    sender: prioritized_mpsc::Sender<QueuedJob<J>>,
+    jobs: SourceManager<J, Box<dyn RecurringJob<J> + Send>>,
+    /// the job assigned to each worker slot, along with when it was assigned
+    slots: Vec<Option<(QueuedJob<J>, Instant)>>,
+    /// the indexes of the slots with a job, in the order they were assigned
+    assigned: VecDeque<usize>,
+    concurrency_limit: Box<ConcurrencyLimitFn<J>>,
+    execution: Execution<J>,
+    clock: Arc<dyn Clock>,
+}
+
+impl<J: Job + 'static> ManualRunner<J> {
+    pub(crate) fn new(
+        sender: prioritized_mpsc::Sender<QueuedJob<J>>,
+        jobs: SourceManager<J, Box<dyn RecurringJob<J> + Send>>,
+        slots: usize,
+        concurrency_limit: Box<ConcurrencyLimitFn<J>>,
+        execution: Execution<J>,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        assert!(slots > 0, "the runner needs at least one worker slot");
+        Self {
+            sender,
+            jobs,
+            slots: (0..slots).map(|_| None).collect(),
+            assigned: VecDeque::new(),
+            concurrency_limit,
+            execution,
+            clock,
+        }
+    }
+
+    /// Send a job to the queue. As waiting for space would never finish, if the queue is full (see [`Builder::queue_capacity`](crate::Builder::queue_capacity)) the job is returned
+    pub fn send(&self, job: J) -> Result<(), crossbeam_channel::TrySendError<J>> {
+        self.sender
+            .try_send(QueuedJob::new(job))
+            .map_err(|err| match err {
+                crossbeam_channel::TrySendError::Full(job) => {
+                    crossbeam_channel::TrySendError::Full(job.into_inner())
+                }
+                crossbeam_channel::TrySendError::Disconnected(job) => {
+                    crossbeam_channel::TrySendError::Disconnected(job.into_inner())
+                }
+            })
+    }
+
+    /// Send a job to the queue like [`ManualRunner::send`], returning a [`JobHandle`] which can be used to check on the job's progress or cancel it
+    pub fn send_tracked(&self, job: J) -> Result<JobHandle<J>, crossbeam_channel::TrySendError<J>> {
+        let (job, tracker) = QueuedJob::tracked(job);
+        let handle = JobHandle::new(&job, tracker, Arc::downgrade(&self.sender.queue()));
+        self.sender.try_send(job).map_err(|err| match err {
+            crossbeam_channel::TrySendError::Full(job) => {
+                crossbeam_channel::TrySendError::Full(job.into_inner())
+            }
+            crossbeam_channel::TrySendError::Disconnected(job) => {
+                crossbeam_channel::TrySendError::Disconnected(job.into_inner())
+            }
+        })?;
+        Ok(handle)
+    }
+
+    /// Send the jobs from the dead letters for which `predicate` returns `true` back to the queue, see [`JobRunner::requeue_dead_letters`](crate::JobRunner::requeue_dead_letters). Jobs which don't fit in the queue are left in the sink
+    pub fn requeue_dead_letters(&self, predicate: impl FnMut(&DeadLetter<J>) -> bool) -> usize {
+        if let Some(dead_letters) = &self.execution.dead_letters {
+            dead_letter::requeue(&**dead_letters, predicate, |job| {
+                self.send(job)
+                    .map_err(crossbeam_channel::TrySendError::into_inner)
+            })
+        } else {
+            0
+        }
+    }
+
+    /// Assign queued jobs to the free slots, then execute the job which was assigned first on this thread. If the job panics, the panic is caught and reported like it is by the [`JobRunner`](crate::JobRunner), see [`Builder::on_panic`](crate::Builder::on_panic). Returns `false` if there was no job to execute
+    pub fn step(&mut self) -> bool {
+        self.assign_jobs();
+        if let Some(index) = self.assigned.pop_front() {
+            if let Some((job, _)) = self.slots[index].take() {
+                self.execution.run(job, index);
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Step until there are no more jobs to execute, including any recurring jobs which become ready whilst running. Returns how many jobs were executed
+    pub fn run_until_idle(&mut self) -> usize {
+        let mut executed = 0;
+        while self.step() {
+            executed += 1;
+        }
+        executed
+    }
+
+    /// Get the jobs waiting to be executed, grouped by priority with the highest priority first, along with the status of each of the worker slots, slots with a job are [`WorkerStatus::Working`]
+    pub fn snapshot(&self) -> Snapshot<J>
+    where
+        J: Clone,
+    {
+        Snapshot {
+            queue: queue_snapshot(&self.sender),
+            workers: self
+                .slots
+                .iter()
+                .map(|slot| match slot {
+                    Some((job, started)) => WorkerStatus::Working {
+                        exclusion: job.exclusion(),
+                        started: *started,
+                    },
+                    None => WorkerStatus::Available,
+                })
+                .collect(),
+        }
+    }
+
+    /// Get counts of what has happened to the jobs since the runner was built
+    pub fn metrics(&self) -> Metrics {
+        self.execution.expiry.metrics()
+    }
+
+    /// Handles on each of the recurring jobs set on the [`Builder`](crate::Builder), which can be used to control them
+    pub fn recurring_handles(&self) -> Vec<RecurringHandle> {
+        self.jobs.schedules().handles()
+    }
+
+    /// Assign jobs to the free slots in priority order, skipping those which would exceed their concurrency limit or which share an exclusion with a job which has already been assigned. Jobs which have expired are removed first
+    fn assign_jobs(&mut self) {
+        let mut exclusions: Vec<_> = self
+            .slots
+            .iter()
+            .flatten()
+            .map(|(job, _)| job.exclusion())
+            .collect();
+        let mut working_count = exclusions.len();
+        let mut jobs = self.jobs.try_get();
+        let expired = self.execution.expiry.take_expired(&mut jobs);
+        while working_count < self.slots.len() {
+            let job = if let Some(job) = jobs.maybe_next() {
+                job
+            } else {
+                break;
+            };
+            if let Some(max_concurrency) = (self.concurrency_limit)(job.priority()) {
+                if working_count as u8 >= max_concurrency {
+                    continue;
+                }
+            }
+            if exclusions.contains(&job.exclusion()) {
+                continue;
+            }
+            working_count += 1;
+            exclusions.push(job.exclusion());
+            let index = self
+                .slots
+                .iter()
+                .position(Option::is_none)
+                .expect("a slot is free");
+            self.slots[index] = Some((job.into_inner(), self.clock.now()));
+            self.assigned.push_back(index);
+        }
+        drop(jobs);
+        self.execution.expiry.expired(expired);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use crate::{ExclusionOption, JobRunner, MergeResult, RecurrableJob, TestClock};
+
+    use super::*;
+
+    #[derive(Debug, Clone)]
+    struct Tester {
+        key: char,
+        priority: u8,
+        exclusion: Option<u8>,
+        executed: Arc<Mutex<String>>,
+    }
+
+    impl Job for Tester {
+        type Exclusion = ExclusionOption<u8>;
+
+        fn exclusion(&self) -> Self::Exclusion {
+            self.exclusion.into()
+        }
+
+        type Priority = u8;
+
+        fn priority(&self) -> Self::Priority {
+            self.priority
+        }
+
+        fn execute(self) {
+            self.executed.lock().unwrap().push(self.key);
+        }
+    }
+
+    impl RecurrableJob for Tester {
+        fn matches(&self, other: &Self) -> bool {
+            self.key == other.key
+        }
+    }
+
+    fn tester(
+        executed: &Arc<Mutex<String>>,
+        key: char,
+        priority: u8,
+        exclusion: Option<u8>,
+    ) -> Tester {
+        Tester {
+            key,
+            priority,
+            exclusion,
+            executed: executed.clone(),
+        }
+    }
+
+    #[test]
+    fn exclusion_waits() {
+        let executed = Arc::default();
+        let mut runner = JobRunner::builder().build_manual(2);
+        runner.send(tester(&executed, 'a', 1, Some(1))).unwrap();
+        runner.send(tester(&executed, 'b', 1, Some(1))).unwrap();
+        runner.send(tester(&executed, 'c', 1, Some(2))).unwrap();
+        assert!(runner.step());
+        assert_eq!(*executed.lock().unwrap(), "a");
+        // b is assigned once a has finished, c was already running
+        assert!(runner.step());
+        assert!(matches!(
+            runner.snapshot().workers[..],
+            [
+                WorkerStatus::Working {
+                    exclusion: ExclusionOption::Some(1),
+                    ..
+                },
+                WorkerStatus::Available
+            ]
+        ));
+        assert_eq!(runner.run_until_idle(), 1);
+        assert_eq!(*executed.lock().unwrap(), "acb");
+        assert!(!runner.step());
+    }
+
+    #[test]
+    fn concurrency_limited() {
+        let executed = Arc::default();
+        let mut runner = JobRunner::builder()
+            .limit_concurrency(|priority| (priority == 1).then_some(1))
+            .build_manual(3);
+        runner.send(tester(&executed, 'a', 1, None)).unwrap();
+        runner.send(tester(&executed, 'b', 1, None)).unwrap();
+        runner.send(tester(&executed, 'c', 2, None)).unwrap();
+        assert!(runner.step());
+        assert_eq!(*executed.lock().unwrap(), "c");
+        // neither a nor b were assigned whilst c was running, although there were free slots
+        assert_eq!(runner.snapshot().queue[0].1.len(), 2);
+        assert_eq!(runner.run_until_idle(), 2);
+        assert_eq!(*executed.lock().unwrap(), "cab");
+    }
+
+    #[test]
+    fn merged() {
+        let executed = Arc::default();
+        let mut runner = JobRunner::builder()
+            .enable_merge(|job: Tester, into| {
+                if job.key == into.key {
+                    MergeResult::Success
+                } else {
+                    MergeResult::NotMerged(job)
+                }
+            })
+            .build_manual(1);
+        runner.send(tester(&executed, 'a', 1, None)).unwrap();
+        runner.send(tester(&executed, 'b', 1, None)).unwrap();
+        runner.send(tester(&executed, 'a', 1, None)).unwrap();
+        assert_eq!(runner.run_until_idle(), 2);
+        assert_eq!(*executed.lock().unwrap(), "ab");
+    }
+
+    #[test]
+    fn recurring() {
+        let executed = Arc::default();
+        let clock = TestClock::new();
+        let mut runner = JobRunner::builder()
+            .clock(clock.clone())
+            .set_recurring(
+                Duration::from_secs(60),
+                clock.now(),
+                tester(&executed, 'r', 1, None),
+            )
+            .build_manual(1);
+        assert_eq!(runner.run_until_idle(), 0);
+        clock.advance(Duration::from_secs(61));
+        assert_eq!(runner.run_until_idle(), 1);
+        assert_eq!(runner.run_until_idle(), 0);
+        assert_eq!(*executed.lock().unwrap(), "r");
+    }
+}
@@ -0,0 +1,314 @@
+//! Jobs which can fail and be retried, see [`FallibleJob`] and [`Builder::retry_policy`](crate::Builder::retry_policy)
+
+use std::{fmt, sync::Arc, time::Duration};
+
+use crate::{
+    clock::Clock,
+    handle::{Executed, QueuedJob},
+    source::delayed::Delayed,
+    DeadLetterReason, Job,
+};
+
+/// The result of executing a [`FallibleJob`]
+#[derive(Debug)]
+pub enum JobOutcome<J, E> {
+    /// The job succeeded
+    Done,
+    /// The job should be tried again once the backoff of the [`RetryPolicy`] has passed
+    Retry(J),
+    /// The job should be tried again once this delay has passed, rather than the backoff of the [`RetryPolicy`]
+    RetryAfter(J, Duration),
+    /// The job failed and shouldn't be retried
+    Failed(E),
+}
+
+/// A job which can fail and ask to be retried, the runner only uses [`FallibleJob::try_execute`] if it is built with a [`Builder::retry_policy`](crate::Builder::retry_policy), otherwise it uses [`Job::execute`], which can just call `try_execute` and ignore the outcome
+///
+/// ```
+/// use gaffer::{FallibleJob, Job, JobOutcome, JobRunner, NoExclusion, RetryPolicy};
+/// use std::time::Duration;
+///
+/// struct Fetch(&'static str);
+///
+/// impl Job for Fetch {
+///     type Exclusion = NoExclusion;
+///
+///     fn exclusion(&self) -> Self::Exclusion {
+///         NoExclusion
+///     }
+///
+///     type Priority = ();
+///
+///     fn priority(&self) -> Self::Priority {}
+///
+///     fn execute(self) {
+///         let _ = self.try_execute();
+///     }
+/// }
+///
+/// impl FallibleJob for Fetch {
+///     type Error = String;
+///
+///     fn try_execute(self) -> JobOutcome<Self, Self::Error> {
+///         if self.0.starts_with("https://") {
+///             println!("Fetched {}", self.0);
+///             JobOutcome::Done
+///         } else {
+///             JobOutcome::Failed(format!("can't fetch {}", self.0))
+///         }
+///     }
+/// }
+///
+/// let runner = JobRunner::builder()
+///     .retry_policy(
+///         RetryPolicy::default()
+///             .max_attempts(5)
+///             .backoff(Duration::from_millis(100), Duration::from_secs(10)),
+///     )
+///     .build(1);
+/// runner.send(Fetch("https://example.com")).unwrap();
+/// ```
+pub trait FallibleJob: Job + Sized {
+    /// The error the job fails with, it is logged when the job fails and given to the [`Builder::dead_letters`](crate::Builder::dead_letters) sink
+    type Error: fmt::Debug;
+
+    /// Execute and consume the job, returning whether it succeeded or should be retried
+    fn try_execute(self) -> JobOutcome<Self, Self::Error>;
+}
+
+/// How failed jobs are retried, see [`Builder::retry_policy`](crate::Builder::retry_policy)
+///
+/// The backoff doubles with each attempt, from the initial backoff up to the maximum. The default is up to 3 attempts, with a backoff from 1 second up to 1 minute and jitter.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The most times a job is executed, including the first attempt, after which it fails rather than being retried.
+    ///
+    /// Panics if `max_attempts` is 0
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        assert!(max_attempts > 0, "a job needs at least one attempt");
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// The delay before the first retry, after which it doubles each attempt up to `max`
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    /// If set, each delay is picked at random between half and all of the backoff, so that jobs which failed together aren't all retried at the same moment
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// The delay before retrying a job which has been executed `attempts` times
+    fn delay(&self, attempts: u32) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(1 << (attempts - 1).min(31))
+            .min(self.max_backoff);
+        if self.jitter {
+            let half = backoff / 2;
+            let range = (backoff - half).as_nanos().min(u64::MAX.into()) as u64;
+            half + Duration::from_nanos(fastrand::u64(0..=range))
+        } else {
+            backoff
+        }
+    }
+}
+
+/// The retry policy, along with how to execute the jobs it applies to
+pub(crate) struct Retry<J> {
+    policy: RetryPolicy,
+    /// executes the job, with any error formatted
+    execute: fn(J) -> JobOutcome<J, String>,
+}
+
+impl<J: FallibleJob> Retry<J> {
+    pub fn new(policy: RetryPolicy) -> Self {
+        Self {
+            policy,
+            execute: |job| match job.try_execute() {
+                JobOutcome::Done => JobOutcome::Done,
+                JobOutcome::Retry(job) => JobOutcome::Retry(job),
+                JobOutcome::RetryAfter(job, delay) => JobOutcome::RetryAfter(job, delay),
+                JobOutcome::Failed(error) => JobOutcome::Failed(format!("{:?}", error)),
+            },
+        }
+    }
+}
+
+impl<J> Retry<J> {
+    /// Retry jobs by adding them to `delayed`, with their delays measured by `clock`
+    pub fn retrier(self, delayed: Arc<Delayed<J>>, clock: Arc<dyn Clock>) -> Retrier<J> {
+        Retrier {
+            retry: self,
+            delayed,
+            clock,
+        }
+    }
+}
+
+/// Executes jobs with [`FallibleJob::try_execute`], sending those which ask to be retried back to the queue once their delay has passed
+pub(crate) struct Retrier<J> {
+    retry: Retry<J>,
+    delayed: Arc<Delayed<J>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl<J: Job> Retrier<J> {
+    /// Execute `job` on the worker with index `worker_index`, if it asks to be retried and it has attempts left, it is sent to be enqueued again. If it failed permanently, returns why, along with the job if it wasn't consumed
+    pub fn run(
+        &self,
+        job: QueuedJob<J>,
+        worker_index: usize,
+    ) -> Option<(Option<J>, DeadLetterReason)> {
+        let policy = &self.retry.policy;
+        let attempts = job.attempts + 1;
+        let mut delay = Duration::ZERO;
+        let mut failed = None;
+        let retry = job.attempt(worker_index, |job| match (self.retry.execute)(job) {
+            JobOutcome::Done => Executed::Done,
+            JobOutcome::Retry(job) if attempts < policy.max_attempts => {
+                delay = policy.delay(attempts);
+                Executed::Retry(job)
+            }
+            JobOutcome::RetryAfter(job, after) if attempts < policy.max_attempts => {
+                delay = after;
+                Executed::Retry(job)
+            }
+            JobOutcome::Retry(job) | JobOutcome::RetryAfter(job, _) => {
+                log::warn!("Job failed after {} attempts", attempts);
+                failed = Some((Some(job), DeadLetterReason::RetriesExhausted));
+                Executed::Failed
+            }
+            JobOutcome::Failed(error) => {
+                log::warn!("Job failed after {} attempts: {}", attempts, error);
+                failed = Some((None, DeadLetterReason::Failed(error)));
+                Executed::Failed
+            }
+        });
+        if let Some(job) = retry {
+            self.delayed.add(self.clock.now() + delay, job);
+        }
+        failed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex;
+
+    use crate::{JobRunner, JobStatus, NoExclusion, TestClock};
+
+    use super::*;
+
+    /// fails this many more times before it succeeds
+    #[derive(Clone)]
+    struct Flaky(u32, Arc<Mutex<String>>);
+
+    impl Job for Flaky {
+        type Exclusion = NoExclusion;
+
+        fn exclusion(&self) -> Self::Exclusion {
+            NoExclusion
+        }
+
+        type Priority = ();
+
+        fn priority(&self) -> Self::Priority {}
+
+        fn execute(self) {
+            let _ = self.try_execute();
+        }
+    }
+
+    impl FallibleJob for Flaky {
+        type Error = ();
+
+        fn try_execute(self) -> JobOutcome<Self, Self::Error> {
+            self.1.lock().unwrap().push_str(&self.0.to_string());
+            if self.0 > 0 {
+                JobOutcome::Retry(Flaky(self.0 - 1, self.1))
+            } else {
+                JobOutcome::Done
+            }
+        }
+    }
+
+    #[test]
+    fn backoff_doubles() {
+        let policy = RetryPolicy::default()
+            .backoff(Duration::from_secs(1), Duration::from_secs(5))
+            .jitter(false);
+        let delays: Vec<_> = (1..=5).map(|attempts| policy.delay(attempts)).collect();
+        assert_eq!(delays, [1, 2, 4, 5, 5].map(Duration::from_secs));
+        let policy = policy.jitter(true);
+        for _ in 0..100 {
+            let delay = policy.delay(3);
+            assert!(delay >= Duration::from_secs(2) && delay <= Duration::from_secs(4));
+        }
+        assert!(policy.delay(u32::MAX) <= Duration::from_secs(5));
+    }
+
+    #[test]
+    fn retried() {
+        let executed = Arc::default();
+        let clock = TestClock::new();
+        let mut runner = JobRunner::builder()
+            .clock(clock.clone())
+            .retry_policy(
+                RetryPolicy::default()
+                    .backoff(Duration::from_secs(1), Duration::from_secs(60))
+                    .jitter(false),
+            )
+            .build_manual(1);
+        let succeeds = runner
+            .send_tracked(Flaky(1, Arc::clone(&executed)))
+            .unwrap();
+        assert_eq!(runner.run_until_idle(), 1);
+        assert_eq!(succeeds.status(), JobStatus::Queued);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(runner.run_until_idle(), 1);
+        assert_eq!(succeeds.status(), JobStatus::Completed);
+        assert_eq!(*executed.lock().unwrap(), "10");
+
+        executed.lock().unwrap().clear();
+        let fails = runner
+            .send_tracked(Flaky(5, Arc::clone(&executed)))
+            .unwrap();
+        assert_eq!(runner.run_until_idle(), 1);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(runner.run_until_idle(), 1);
+        // the backoff has doubled
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(runner.run_until_idle(), 0);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(runner.run_until_idle(), 1);
+        // that was the last of its 3 attempts
+        assert_eq!(fails.status(), JobStatus::Failed);
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(runner.run_until_idle(), 0);
+        assert_eq!(*executed.lock().unwrap(), "543");
+    }
+}
@@ -1,16 +1,27 @@
 use parking_lot::{Mutex, MutexGuard};
 use std::{
-    fmt::Debug,
-    iter,
-    sync::{Arc, Barrier},
+    any::Any,
+    fmt::{self, Debug},
+    iter, mem,
+    ops::DerefMut,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Barrier,
+    },
     thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
 use crossbeam_channel::SendError;
 
 use crate::{
+    clock::Clock,
+    dead_letter::{DeadLetter, DeadLetterReason, DeadLetterSink},
+    handle::QueuedJob,
+    retry::Retrier,
     source::{
-        util::{may_be_taken::SkipIterator, PriorityQueue},
+        util::{may_be_taken::SkipIterator, prioritized_mpsc::Waker, Drain, PriorityQueue},
         RecurringJob, SourceManager,
     },
     Job, Prioritised,
@@ -20,37 +31,482 @@ use crate::{
 pub(crate) type ConcurrencyLimitFn<J> =
     dyn Fn(<J as Prioritised>::Priority) -> Option<u8> + Send + Sync;
 
-/// Spawn runners on `thread_num` threads, executing jobs from `jobs` and obeying the concurrency limit `concurrency_limit`
+/// Callback function for jobs which expired before they were started, see [`Builder::on_expired`](crate::Builder::on_expired)
+pub(crate) type ExpiredFn<J> = dyn Fn(J) + Send + Sync;
+
+/// Callback function for jobs which panicked, see [`Builder::on_panic`](crate::Builder::on_panic)
+pub(crate) type PanicFn = dyn Fn(JobPanic) + Send + Sync;
+
+/// How the runner should stop, see [`JobRunner::shutdown`](crate::JobRunner::shutdown)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ShutdownMode {
+    /// Execute all the jobs which are already queued, then stop. Recurring jobs stop being scheduled
+    Drain,
+    /// Let jobs which are already running finish, but don't start any jobs from the queue
+    FinishRunning,
+    /// Stop straight away, without waiting for running jobs to finish. The worker threads are left detached, they will exit once they have finished their current job
+    Immediate,
+}
+
+/// What a worker thread is doing, see [`JobRunner::snapshot`](crate::JobRunner::snapshot)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WorkerStatus<E> {
+    /// Waiting for jobs and assigning them to the available workers
+    Supervisor,
+    /// Executing a job with this exclusion, since `started`
+    Working {
+        /// The exclusion of the job being executed
+        exclusion: E,
+        /// When the worker started executing the job
+        started: Instant,
+    },
+    /// Waiting for the supervisor to assign a job
+    Available,
+    /// The worker has exited
+    Stopped,
+}
+
+/// Counts of what has happened to the jobs in a runner since it was built, see [`JobRunner::metrics`](crate::JobRunner::metrics)
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Metrics {
+    /// Jobs which weren't executed as their deadline passed whilst they were waiting, see [`Job::deadline`]
+    pub expired: u64,
+}
+
+/// A job which panicked whilst it was being executed, see [`Builder::on_panic`](crate::Builder::on_panic)
+pub struct JobPanic {
+    /// The job's [`Debug`] representation, taken before it was executed
+    pub job: String,
+    /// The index of the worker which was executing the job
+    pub worker_index: usize,
+    /// The value the job panicked with, usually a `&'static str` or a `String`, see [`std::panic::catch_unwind`]
+    pub payload: Box<dyn Any + Send>,
+}
+
+impl JobPanic {
+    /// The panic's message, if it panicked with a string
+    pub fn message(&self) -> Option<&str> {
+        panic_message(&*self.payload)
+    }
+}
+
+/// The message of a panic with `payload`, if it panicked with a string
+fn panic_message(payload: &(dyn Any + Send)) -> Option<&str> {
+    if let Some(message) = payload.downcast_ref::<&'static str>() {
+        Some(message)
+    } else {
+        payload.downcast_ref::<String>().map(String::as_str)
+    }
+}
+
+impl fmt::Debug for JobPanic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JobPanic")
+            .field("job", &self.job)
+            .field("worker_index", &self.worker_index)
+            .field("message", &self.message())
+            .finish()
+    }
+}
+
+/// Reports the jobs which panic, along with how to describe them
+pub(crate) struct PanicHook<J> {
+    describe: fn(&J) -> String,
+    on_panic: Box<PanicFn>,
+}
+
+impl<J: Debug> PanicHook<J> {
+    pub fn new(on_panic: Box<PanicFn>) -> Self {
+        Self {
+            describe: |job| format!("{:?}", job),
+            on_panic,
+        }
+    }
+}
+
+/// How jobs are executed, along with what happens to those which expire, panic or fail
+pub(crate) struct Execution<J> {
+    pub clock: Arc<dyn Clock>,
+    pub expiry: Expiry<J>,
+    pub on_panic: Option<PanicHook<J>>,
+    pub retry: Option<Retrier<J>>,
+    pub dead_letters: Option<Arc<dyn DeadLetterSink<J>>>,
+}
+
+impl<J: Job> Execution<J> {
+    /// Execute `job` on the worker with index `worker_index`, catching any panic so that the worker can carry on. The panic is reported to `on_panic` if there is one. If there is a `retry` policy, jobs which fail are retried according to it. Jobs which fail permanently or panic are sent to `dead_letters`
+    pub fn run(&self, mut job: QueuedJob<J>, worker_index: usize) {
+        let (id, attempts) = (job.id, job.attempts + 1);
+        let first_attempt = *job
+            .first_attempt
+            .get_or_insert_with(|| self.clock.system_time());
+        let description = self.on_panic.as_ref().map(|hook| (hook.describe)(&job.job));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            if let Some(retry) = &self.retry {
+                retry.run(job, worker_index)
+            } else {
+                job.run(worker_index);
+                None
+            }
+        }));
+        let failure = match result {
+            Ok(failure) => failure,
+            Err(payload) => {
+                let message = panic_message(&*payload).map(str::to_owned);
+                match (&self.on_panic, description) {
+                    (Some(hook), Some(job)) => (hook.on_panic)(JobPanic {
+                        job,
+                        worker_index,
+                        payload,
+                    }),
+                    _ => log::warn!("Job panicked on worker {}", worker_index),
+                }
+                Some((None, DeadLetterReason::Panicked(message)))
+            }
+        };
+        if let (Some(dead_letters), Some((job, reason))) = (&self.dead_letters, failure) {
+            dead_letters.push(DeadLetter {
+                job,
+                id,
+                attempts,
+                reason,
+                first_attempt,
+                failed: self.clock.system_time(),
+            });
+        }
+    }
+}
+
+/// Removes jobs from the queue once their deadline has passed, see [`Job::deadline`]
+pub(crate) struct Expiry<J> {
+    clock: Arc<dyn Clock>,
+    on_expired: Option<Box<ExpiredFn<J>>>,
+    /// how many jobs have expired
+    expired: AtomicU64,
+}
+
+impl<J: Job> Expiry<J> {
+    pub fn new(clock: Arc<dyn Clock>, on_expired: Option<Box<ExpiredFn<J>>>) -> Self {
+        Self {
+            clock,
+            on_expired,
+            expired: AtomicU64::new(0),
+        }
+    }
+
+    /// Remove the jobs which have expired from `jobs`, before any of them have been iterated
+    pub fn take_expired<Q: DerefMut<Target = PriorityQueue<QueuedJob<J>>>>(
+        &self,
+        jobs: &mut Drain<QueuedJob<J>, Q>,
+    ) -> Vec<J> {
+        let now = self.clock.now();
+        let expired: Vec<_> = jobs
+            .remove_where(|job| job.is_expired(now))
+            .into_iter()
+            .map(QueuedJob::expire)
+            .collect();
+        self.expired
+            .fetch_add(expired.len() as u64, Ordering::Relaxed);
+        expired
+    }
+
+    /// Pass the jobs which have expired to the callback, this shouldn't be called with the queue locked as the callback could send more jobs. A panic in the callback is logged, so that it doesn't stop the worker
+    pub fn expired(&self, jobs: Vec<J>) {
+        if let Some(on_expired) = &self.on_expired {
+            for job in jobs {
+                if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| on_expired(job))) {
+                    log::warn!(
+                        "on_expired callback panicked: {}",
+                        panic_message(&*payload).unwrap_or("Box<dyn Any>")
+                    );
+                }
+            }
+        }
+    }
+
+    /// The metrics of the runner
+    pub fn metrics(&self) -> Metrics {
+        Metrics {
+            expired: self.expired.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Limits for scaling the number of workers automatically, see [`Builder::autoscale`](crate::Builder::autoscale)
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Autoscale {
+    pub min: usize,
+    pub max: usize,
+    /// how long a worker can be available without being assigned a job before it exits
+    pub idle_timeout: Duration,
+}
+
+/// Control state shared between all the runners and the [`Pool`]
+pub(crate) struct Control<J> {
+    shutdown: Mutex<Option<ShutdownMode>>,
+    /// how to shut down once every [`JobRunner`](crate::JobRunner) has been dropped
+    drop_mode: ShutdownMode,
+    /// wakes the supervisor so that it notices changes in control state
+    waker: Waker,
+    autoscale: Option<Autoscale>,
+    execution: Execution<J>,
+    /// the threads of the runners, including those started by other runners, so that they can be joined on shutdown
+    threads: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl<J> Control<J> {
+    fn new(
+        waker: Waker,
+        drop_mode: ShutdownMode,
+        autoscale: Option<Autoscale>,
+        execution: Execution<J>,
+    ) -> Self {
+        Self {
+            shutdown: Mutex::new(None),
+            drop_mode,
+            waker,
+            autoscale,
+            execution,
+            threads: Mutex::default(),
+        }
+    }
+
+    /// Start the thread for the worker with `worker_index`, running `f`
+    fn spawn_thread(&self, worker_index: usize, f: impl FnOnce() + Send + 'static) {
+        let thread = thread::Builder::new()
+            .name(format!("gaffer#{}", worker_index))
+            .spawn(f)
+            .unwrap();
+        let mut threads = self.threads.lock();
+        // autoscaled workers come and go, so don't keep the handles of those which have exited
+        threads.retain(|thread| !thread.is_finished());
+        threads.push(thread);
+    }
+
+    /// Wait for every runner thread to exit, including any started whilst waiting
+    fn join_threads(&self) {
+        loop {
+            let threads = mem::take(&mut *self.threads.lock());
+            if threads.is_empty() {
+                return;
+            }
+            for thread in threads {
+                let _ = thread.join();
+            }
+        }
+    }
+
+    /// Whether this is called from one of the runner threads, such as by a job
+    fn on_runner_thread(&self) -> bool {
+        let current = thread::current().id();
+        self.threads
+            .lock()
+            .iter()
+            .any(|thread| thread.thread().id() == current)
+    }
+
+    /// The mode of shutdown, if the runner is shutting down
+    pub fn shutdown_mode(&self) -> Option<ShutdownMode> {
+        *self.shutdown.lock()
+    }
+
+    /// Start shutting down, or change the mode of a shutdown in progress
+    fn shutdown(&self, mode: ShutdownMode) {
+        *self.shutdown.lock() = Some(mode);
+        self.waker.wake();
+    }
+
+    /// whether the runners should stop, rather than continue onto another job
+    fn is_stopping(&self) -> bool {
+        matches!(
+            self.shutdown_mode(),
+            Some(ShutdownMode::FinishRunning | ShutdownMode::Immediate)
+        )
+    }
+}
+
+/// Spawn runners on `thread_num` threads, executing jobs from `jobs` and obeying the concurrency limit `concurrency_limit`. Once all the senders for `jobs` are dropped, the runners shut down according to `drop_mode`. If there is an `autoscale`, the number of threads changes within its limits. Jobs are executed according to `execution`
 pub(crate) fn spawn<J, R: RecurringJob<J> + Send + 'static>(
     thread_num: usize,
     jobs: Arc<Mutex<SourceManager<J, R>>>,
     concurrency_limit: Box<ConcurrencyLimitFn<J>>,
-) -> Vec<JoinHandle<()>>
+    drop_mode: ShutdownMode,
+    autoscale: Option<Autoscale>,
+    execution: Execution<J>,
+) -> Pool<J, R>
 where
     J: Job + 'static,
     <J as Prioritised>::Priority: Send,
 {
-    let queue = jobs.lock().queue();
+    let (queue, waker) = {
+        let jobs = jobs.lock();
+        (jobs.queue(), jobs.waker())
+    };
+    let control = Arc::new(Control::new(waker, drop_mode, autoscale, execution));
+    let (alive, alive_recv) = crossbeam_channel::bounded(0);
+    let concurrency_limit: Arc<ConcurrencyLimitFn<QueuedJob<J>>> = concurrency_limit.into();
+    let runners: Vec<_> = RunnerState::new(
+        thread_num,
+        concurrency_limit.clone(),
+        control.execution.clock.clone(),
+    )
+    .collect();
+    let pool = Pool {
+        jobs,
+        queue,
+        workers: runners
+            .first()
+            .map(|(_, state)| state.workers.clone())
+            .unwrap_or_default(),
+        concurrency_limit,
+        control,
+        alive_send: Mutex::new(Some(alive)),
+        alive: alive_recv,
+    };
     let barrier = Arc::new(Barrier::new(thread_num));
-    RunnerState::new(thread_num, concurrency_limit)
-        .map(move |(recv, state)| {
-            let jobs = jobs.clone();
-            let queue = queue.clone();
-            let barrier = barrier.clone();
-            thread::Builder::new()
-                .name(format!("gaffer#{}", state.worker_index))
-                .spawn(move || {
-                    Runner::new(state, jobs, queue).run(barrier, recv);
-                })
-                .unwrap()
-        })
-        .collect()
+    for (recv, state) in runners {
+        let barrier = barrier.clone();
+        pool.spawn_runner(state, move |runner| runner.run(barrier, recv));
+    }
+    pool
+}
+
+/// Handle on the runner threads, used to add more or to shut them down
+pub(crate) struct Pool<J: Job + 'static, R> {
+    jobs: Arc<Mutex<SourceManager<J, R>>>,
+    queue: Arc<Mutex<PriorityQueue<QueuedJob<J>>>>,
+    workers: Arc<Mutex<Vec<WorkerState<QueuedJob<J>>>>>,
+    concurrency_limit: Arc<ConcurrencyLimitFn<QueuedJob<J>>>,
+    control: Arc<Control<J>>,
+    /// cloned into each new runner, taken once shutting down so that `alive` can disconnect
+    alive_send: Mutex<Option<crossbeam_channel::Sender<()>>>,
+    /// never receives anything, disconnects once every runner has exited
+    alive: crossbeam_channel::Receiver<()>,
+}
+
+impl<J, R> Pool<J, R>
+where
+    J: Job + 'static,
+    R: RecurringJob<J> + Send + 'static,
+{
+    /// Start a thread for a runner with `state`, which then runs `f`. Does nothing if the pool is shutting down
+    fn spawn_runner(
+        &self,
+        state: RunnerState<QueuedJob<J>>,
+        f: impl FnOnce(Runner<J, R>) + Send + 'static,
+    ) {
+        let alive = if let Some(alive) = &*self.alive_send.lock() {
+            alive.clone()
+        } else {
+            return;
+        };
+        let runner = Runner::new(
+            state,
+            self.jobs.clone(),
+            self.queue.clone(),
+            self.control.clone(),
+            alive,
+        );
+        self.control
+            .spawn_thread(runner.state.worker_index, move || f(runner));
+    }
+
+    /// Change the number of worker threads to `thread_num`. Extra threads start straight away, threads which are no longer needed exit once they have finished their current job
+    pub fn set_threads(&self, thread_num: usize) {
+        if self.is_shutdown() {
+            return;
+        }
+        let added = resize_workers(&mut self.workers.lock(), thread_num);
+        for (worker_index, recv) in added {
+            let state = RunnerState {
+                workers: self.workers.clone(),
+                worker_index,
+                concurrency_limit: self.concurrency_limit.clone(),
+                clock: self.control.execution.clock.clone(),
+            };
+            self.spawn_runner(state, move |runner| runner.run_added(recv));
+        }
+        // the supervisor might have jobs it can now assign
+        self.control.waker.wake();
+    }
+
+    /// Whether a shutdown has been started
+    pub fn is_shutdown(&self) -> bool {
+        self.control.shutdown_mode().is_some()
+    }
+
+    /// Stop the runners, waiting for them up to `timeout` according to `mode`, returns the jobs which were never executed. The threads are only joined if the runners stopped in time
+    pub fn shutdown(&self, mode: ShutdownMode, timeout: Option<Duration>) -> Vec<J> {
+        // the runner thread would wait for itself to exit
+        assert!(
+            mode == ShutdownMode::Immediate || timeout.is_some() || !self.control.on_runner_thread(),
+            "a job can't wait indefinitely for the runner to shut down, it needs a timeout or `ShutdownMode::Immediate`"
+        );
+        self.control.shutdown(mode);
+        self.alive_send.lock().take();
+        let stopped = mode != ShutdownMode::Immediate && self.wait(timeout);
+        if !stopped {
+            // whatever is left won't be executed
+            self.control.shutdown(ShutdownMode::Immediate);
+        }
+        let remaining = self.jobs.lock().take_all();
+        if stopped {
+            self.control.join_threads();
+        }
+        remaining
+    }
+
+    /// The metrics of the runner
+    pub fn metrics(&self) -> Metrics {
+        self.control.execution.expiry.metrics()
+    }
+
+    /// The status of each of the workers, in order of their index
+    pub fn workers(&self) -> Vec<WorkerStatus<J::Exclusion>> {
+        self.workers
+            .lock()
+            .iter()
+            .map(WorkerState::status)
+            .collect()
+    }
+
+    /// Wait for all the runners to exit, returns `false` if the `timeout` passed first
+    fn wait(&self, timeout: Option<Duration>) -> bool {
+        let result = if let Some(timeout) = timeout {
+            self.alive.recv_deadline(Instant::now() + timeout)
+        } else {
+            self.alive
+                .recv()
+                .map_err(|_| crossbeam_channel::RecvTimeoutError::Disconnected)
+        };
+        matches!(
+            result,
+            Err(crossbeam_channel::RecvTimeoutError::Disconnected)
+        )
+    }
+}
+
+impl<J: Job + 'static, R> Drop for Pool<J, R> {
+    fn drop(&mut self) {
+        self.alive_send.lock().take();
+        // the last `JobRunner` has been dropped
+        let mut shutdown = self.control.shutdown.lock();
+        if shutdown.is_none() {
+            log::debug!("Runner dropped, shutting down");
+            *shutdown = Some(self.control.drop_mode);
+            self.control.waker.wake();
+        }
+    }
 }
 
 struct Runner<J: Job + 'static, R: RecurringJob<J> + Send + 'static> {
-    state: RunnerState<J>,
+    state: RunnerState<QueuedJob<J>>,
     jobs: Arc<Mutex<SourceManager<J, R>>>,
-    queue: Arc<Mutex<PriorityQueue<J>>>,
+    queue: Arc<Mutex<PriorityQueue<QueuedJob<J>>>>,
+    control: Arc<Control<J>>,
+    /// dropped when the runner exits
+    alive: crossbeam_channel::Sender<()>,
 }
 
 impl<J, R> Runner<J, R>
@@ -59,63 +515,197 @@ where
     R: RecurringJob<J> + Send,
 {
     fn new(
-        state: RunnerState<J>,
+        state: RunnerState<QueuedJob<J>>,
         jobs: Arc<Mutex<SourceManager<J, R>>>,
-        queue: Arc<Mutex<PriorityQueue<J>>>,
+        queue: Arc<Mutex<PriorityQueue<QueuedJob<J>>>>,
+        control: Arc<Control<J>>,
+        alive: crossbeam_channel::Sender<()>,
     ) -> Self {
-        Self { state, jobs, queue }
+        Self {
+            state,
+            jobs,
+            queue,
+            control,
+            alive,
+        }
     }
 
     /// Run the runner loop, `ready_barrier` syncronizes with the start of the other runners and decides the initial supervisor
-    fn run(self, ready_barrier: Arc<Barrier>, recv: crossbeam_channel::Receiver<J>) -> ! {
+    fn run(self, ready_barrier: Arc<Barrier>, recv: crossbeam_channel::Receiver<QueuedJob<J>>) {
         let job = if ready_barrier.wait().is_leader() {
             // become the supervisor
             self.state.become_supervisor();
             self.run_supervisor()
         } else {
-            // worker is available
-            recv.recv()
-                .expect("Available worker is not connected to shared runner state")
+            // worker is available, until the supervisor disconnects it during shutdown
+            return self.run_available(recv);
         };
         drop(recv);
-        self.run_worker(job);
+        if let Some(job) = job {
+            self.run_worker(job);
+        }
+    }
+
+    /// Run a runner which was added to an existing pool, it is available if it has a `recv`, otherwise it has been made the supervisor
+    fn run_added(self, recv: Option<crossbeam_channel::Receiver<QueuedJob<J>>>) {
+        if let Some(recv) = recv {
+            self.run_available(recv);
+        } else if let Some(job) = self.run_supervisor() {
+            self.run_worker(job);
+        }
+    }
+
+    /// Run a worker which is available, once it is assigned a job it executes it
+    fn run_available(self, recv: crossbeam_channel::Receiver<QueuedJob<J>>) {
+        if let Some(job) = self.wait_available(recv) {
+            self.run_worker(job);
+        }
     }
 
-    fn run_worker(self, mut job: J) -> ! {
+    /// Wait for the supervisor to assign a job, `None` if the runner should exit, because it is shutting down or because it has been idle and isn't needed
+    fn wait_available(
+        &self,
+        recv: crossbeam_channel::Receiver<QueuedJob<J>>,
+    ) -> Option<QueuedJob<J>> {
+        let autoscale = if let Some(autoscale) = &self.control.autoscale {
+            autoscale
+        } else {
+            return recv.recv().ok();
+        };
         loop {
-            job.execute(); // so a panicking job doesn't kill workers
-            job = self.next_job();
+            match recv.recv_timeout(autoscale.idle_timeout) {
+                Ok(job) => return Some(job),
+                Err(crossbeam_channel::RecvTimeoutError::Disconnected) => return None,
+                Err(crossbeam_channel::RecvTimeoutError::Timeout) => {
+                    if self.state.retire_idle(autoscale.min) {
+                        return None;
+                    }
+                }
+            }
         }
     }
 
-    fn next_job(&self) -> J {
-        let transition = self.state.completed_job(self.queue.lock().drain());
+    fn run_worker(self, mut job: QueuedJob<J>) {
+        loop {
+            self.control.execution.run(job, self.state.worker_index);
+            if let Some(next) = self.next_job() {
+                job = next;
+            } else {
+                return;
+            }
+        }
+    }
+
+    /// Find the next job for this worker, `None` if the runner should exit
+    fn next_job(&self) -> Option<QueuedJob<J>> {
+        if self.control.is_stopping() {
+            self.state.stop();
+            return None;
+        }
+        let (transition, expired) = {
+            let mut queue = self.queue.lock();
+            let mut jobs = queue.drain();
+            let expired = self.control.execution.expiry.take_expired(&mut jobs);
+            (self.state.completed_job(jobs), expired)
+        };
+        self.control.execution.expiry.expired(expired);
+        if self.control.shutdown_mode().is_some() {
+            // the supervisor might be waiting for the queue to be drained
+            self.control.waker.wake();
+        }
         match transition {
-            PostJobTransition::BecomeAvailable(recv) => recv
-                .recv()
-                .expect("Available worker is not connected to shared runner state"),
+            PostJobTransition::BecomeAvailable(recv) => self.wait_available(recv),
             PostJobTransition::BecomeSupervisor => self.run_supervisor(),
-            PostJobTransition::KeepWorking(job) => job,
+            PostJobTransition::KeepWorking(job) => Some(job),
+            PostJobTransition::Retire => None,
         }
     }
 
-    /// Run the supervisor loop, jobs are retrieved and assigned. Returns when the supervisor has a job to execute and it becomes a worker
-    fn run_supervisor(&self) -> J {
+    /// Run the supervisor loop, jobs are retrieved and assigned. Returns when the supervisor has a job to execute and it becomes a worker, or `None` if the runner is shutting down
+    fn run_supervisor(&self) -> Option<QueuedJob<J>> {
         let mut wait_for_new = false;
         let mut jobs = self.jobs.lock();
         loop {
-            if let Some(job) = self.state.assign_jobs(jobs.get(wait_for_new)) {
-                // become a worker
-                return job;
+            let draining = match self.control.shutdown_mode() {
+                None => false,
+                Some(ShutdownMode::Drain) => {
+                    jobs.close();
+                    true
+                }
+                Some(ShutdownMode::FinishRunning | ShutdownMode::Immediate) => break,
+            };
+            if draining && jobs.is_empty() {
+                // don't wait for new jobs, none will be sent
+                break;
+            }
+            let mut ready = jobs.get(wait_for_new);
+            let expired = self.control.execution.expiry.take_expired(&mut ready);
+            let assigned = self.state.assign_jobs(ready);
+            if !expired.is_empty() {
+                MutexGuard::unlocked(&mut jobs, || self.control.execution.expiry.expired(expired));
+            }
+            if let Some(job) = assigned {
+                match self.scale_up(job) {
+                    Ok(()) => {
+                        // there could be more jobs to assign
+                        wait_for_new = false;
+                        continue;
+                    }
+                    // become a worker
+                    Err(job) => return Some(job),
+                }
             }
             wait_for_new = true;
         }
+        self.state.stop_supervising();
+        None
+    }
+
+    /// If autoscaling allows another worker, start one to execute the `job` which the supervisor would otherwise have had to execute itself, otherwise returns the `job`
+    fn scale_up(&self, job: QueuedJob<J>) -> Result<(), QueuedJob<J>> {
+        let autoscale = match &self.control.autoscale {
+            Some(autoscale) if self.control.shutdown_mode().is_none() => autoscale,
+            _ => return Err(job),
+        };
+        let worker_index = if let Some(worker_index) = self.state.hand_off(autoscale.max) {
+            worker_index
+        } else {
+            return Err(job);
+        };
+        log::debug!(
+            "{}: Supervisor starting worker {} to take a job",
+            std::thread::current().name().unwrap_or_default(),
+            worker_index
+        );
+        let runner = Runner::new(
+            RunnerState {
+                workers: self.state.workers.clone(),
+                worker_index,
+                concurrency_limit: self.state.concurrency_limit.clone(),
+                clock: self.state.clock.clone(),
+            },
+            self.jobs.clone(),
+            self.queue.clone(),
+            self.control.clone(),
+            self.alive.clone(),
+        );
+        self.control
+            .spawn_thread(worker_index, move || runner.run_worker(job));
+        Ok(())
     }
 
-    /// Entry point for a new thread, replacing one which panicked whilst executing a job
-    fn panic_recover(self) -> ! {
-        let job = self.next_job();
-        self.run_worker(job);
+    /// Entry point for a new thread, replacing one which panicked outside of a job, such as in a [`RecurringJob`]. The job it was executing, if any, is lost
+    fn panic_recover(self) {
+        match self.state.recover() {
+            PostJobTransition::BecomeAvailable(recv) => self.run_available(recv),
+            PostJobTransition::BecomeSupervisor => {
+                if let Some(job) = self.run_supervisor() {
+                    self.run_worker(job);
+                }
+            }
+            PostJobTransition::KeepWorking(job) => self.run_worker(job),
+            PostJobTransition::Retire => {}
+        }
     }
 }
 
@@ -129,22 +719,27 @@ impl<J: Job + 'static, R: RecurringJob<J> + Send + 'static> Drop for Runner<J, R
                         workers,
                         worker_index,
                         concurrency_limit,
+                        clock,
                     },
                 jobs,
                 queue,
+                control,
+                alive,
             } = self;
             let state = RunnerState {
                 workers: workers.clone(),
                 worker_index: *worker_index,
                 concurrency_limit: concurrency_limit.clone(),
+                clock: clock.clone(),
             };
-            let runner = Runner::new(state, jobs.clone(), queue.clone());
-            thread::Builder::new()
-                .name(format!("gaffer#{}", worker_index))
-                .spawn(move || {
-                    runner.panic_recover();
-                })
-                .unwrap();
+            let runner = Runner::new(
+                state,
+                jobs.clone(),
+                queue.clone(),
+                control.clone(),
+                alive.clone(),
+            );
+            control.spawn_thread(*worker_index, move || runner.panic_recover());
         }
     }
 }
@@ -153,12 +748,15 @@ struct RunnerState<J: Job> {
     workers: Arc<Mutex<Vec<WorkerState<J>>>>,
     worker_index: usize,
     concurrency_limit: Arc<ConcurrencyLimitFn<J>>,
+    /// tells the time that workers start jobs
+    clock: Arc<dyn Clock>,
 }
 
 impl<J: Job> RunnerState<J> {
     pub fn new(
         num: usize,
         concurrency_limit: impl Into<Arc<ConcurrencyLimitFn<J>>>,
+        clock: Arc<dyn Clock>,
     ) -> impl Iterator<Item = (crossbeam_channel::Receiver<J>, Self)> {
         let (receivers, worker_state): (Vec<_>, _) =
             iter::repeat_with(WorkerState::available).take(num).unzip();
@@ -171,6 +769,7 @@ impl<J: Job> RunnerState<J> {
                     workers: worker_state.clone(),
                     worker_index: idx,
                     concurrency_limit: concurrency_limit.clone(),
+                    clock: clock.clone(),
                 },
             )
         })
@@ -193,6 +792,14 @@ impl<J: Job> RunnerState<J> {
             "{}: Job completed by worker",
             std::thread::current().name().unwrap_or_default()
         );
+        if let WorkerState::Retiring(..) = workers[self.worker_index] {
+            log::trace!(
+                "{}: > Worker no longer needed, retiring",
+                std::thread::current().name().unwrap_or_default()
+            );
+            workers[self.worker_index] = WorkerState::Stopped;
+            return PostJobTransition::Retire;
+        }
         let working_count = workers.iter().filter(|state| state.is_working()).count() - 1; // not including self
         while let Some(job) = jobs.maybe_next() {
             if let Some(max_concurrency) = (self.concurrency_limit)(job.priority()) {
@@ -216,7 +823,9 @@ impl<J: Job> RunnerState<J> {
                 );
                 continue;
             }
-            return PostJobTransition::KeepWorking(job.into_inner());
+            let job = job.into_inner();
+            workers[self.worker_index] = WorkerState::working(job.exclusion(), self.clock.now());
+            return PostJobTransition::KeepWorking(job);
         }
         if workers.iter().any(|worker| worker.is_supervisor()) {
             let (send, recv) = crossbeam_channel::bounded(1);
@@ -236,6 +845,34 @@ impl<J: Job> RunnerState<J> {
         }
     }
 
+    /// perform state transition for a worker replacing one which panicked, whatever state it was in. It becomes the supervisor if there isn't another one, otherwise it becomes available. A worker which was retiring or stopped exits
+    fn recover(&self) -> PostJobTransition<J> {
+        let mut workers = self.workers();
+        log::debug!(
+            "{}: Worker recovering from panic",
+            std::thread::current().name().unwrap_or_default()
+        );
+        if matches!(
+            workers[self.worker_index],
+            WorkerState::Retiring(..) | WorkerState::Stopped
+        ) {
+            workers[self.worker_index] = WorkerState::Stopped;
+            return PostJobTransition::Retire;
+        }
+        let other_supervisor = workers
+            .iter()
+            .enumerate()
+            .any(|(index, worker)| index != self.worker_index && worker.is_supervisor());
+        if other_supervisor {
+            let (recv, state) = WorkerState::available();
+            workers[self.worker_index] = state;
+            PostJobTransition::BecomeAvailable(recv)
+        } else {
+            workers[self.worker_index] = WorkerState::Supervisor;
+            PostJobTransition::BecomeSupervisor
+        }
+    }
+
     /// assigns jobs to available workers, changing those workers into the `Working` state.
     /// jobs are allocated to workers in order. jobs which clash with running exclusions are skipped. jobs whose priorities indicate a max number of threads below the number of working threads are skipped.
     /// skipped threads are dropped
@@ -273,7 +910,7 @@ impl<J: Job> RunnerState<J> {
                         if let Err(SendError(returned_job)) = send.send(job) {
                             job = returned_job; // if a worker has died, the rest of the workers can continue
                         } else {
-                            *worker = WorkerState::Working(exclusion);
+                            *worker = WorkerState::working(exclusion, self.clock.now());
                             break;
                         }
                     } else {
@@ -281,7 +918,8 @@ impl<J: Job> RunnerState<J> {
                     }
                 } else {
                     // no available worker for this job, supervisor to become worker
-                    workers[self.worker_index] = WorkerState::Working(job.exclusion());
+                    workers[self.worker_index] =
+                        WorkerState::working(job.exclusion(), self.clock.now());
                     return Some(job);
                 }
             }
@@ -289,23 +927,151 @@ impl<J: Job> RunnerState<J> {
         None
     }
 
+    /// the supervisor, which has just taken a job to become a worker, hands that job to a new worker instead and remains the supervisor, as long as that doesn't make more than `max` workers. Returns the index of the new worker
+    ///
+    /// panics if this worker is not working
+    fn hand_off(&self, max: usize) -> Option<usize> {
+        let mut workers = self.workers();
+        let (exclusion, started) =
+            if let WorkerState::Working(exclusion, started) = workers[self.worker_index] {
+                (exclusion, started)
+            } else {
+                panic!("only a worker which has just taken a job can hand it off");
+            };
+        if workers.iter().filter(|worker| worker.is_active()).count() >= max {
+            return None;
+        }
+        let worker_index = free_slot(&mut workers);
+        workers[worker_index] = WorkerState::Working(exclusion, started);
+        workers[self.worker_index] = WorkerState::Supervisor;
+        Some(worker_index)
+    }
+
+    /// an available worker which has been idle exits, unless that would leave fewer than `min` workers. Returns whether it exited
+    fn retire_idle(&self, min: usize) -> bool {
+        let mut workers = self.workers();
+        if !matches!(workers[self.worker_index], WorkerState::Available(_))
+            || workers.iter().filter(|worker| worker.is_active()).count() <= min
+        {
+            return false;
+        }
+        log::debug!(
+            "{}: Worker idle, retiring",
+            std::thread::current().name().unwrap_or_default()
+        );
+        workers[self.worker_index] = WorkerState::Stopped;
+        true
+    }
+
+    /// this worker exits without taking another job
+    fn stop(&self) {
+        self.workers()[self.worker_index] = WorkerState::Stopped;
+    }
+
+    /// the supervisor exits, also stopping the available workers, as there will be no supervisor to assign them jobs
+    ///
+    /// panics if this worker is not the supervisor
+    fn stop_supervising(&self) {
+        let mut workers = self.workers();
+        assert!(workers[self.worker_index].is_supervisor());
+        for worker in workers.iter_mut() {
+            if matches!(worker, WorkerState::Supervisor | WorkerState::Available(_)) {
+                *worker = WorkerState::Stopped; // dropping the sender disconnects the available worker
+            }
+        }
+    }
+
     fn workers(&self) -> MutexGuard<'_, Vec<WorkerState<J>>> {
         self.workers.lock()
     }
 }
 
+/// Change the number of active workers to `thread_num`, reactivating workers which are retiring, or reusing the slots of stopped workers before adding new ones. Returns the indexes of the workers which need to be started, with the receiver of an available worker, or `None` if the worker has been made the supervisor. Available workers are removed before working ones, the supervisor is never removed.
+///
+/// panics if `thread_num` is 0
+fn resize_workers<J: Job>(
+    workers: &mut Vec<WorkerState<J>>,
+    thread_num: usize,
+) -> Vec<(usize, Option<crossbeam_channel::Receiver<J>>)> {
+    assert!(thread_num > 0, "the pool needs at least one thread");
+    let active = workers.iter().filter(|worker| worker.is_active()).count();
+    let mut added = vec![];
+    if thread_num > active {
+        let mut needed = thread_num - active;
+        for worker in workers.iter_mut() {
+            if needed == 0 {
+                break;
+            }
+            if let WorkerState::Retiring(exclusion, started) = *worker {
+                *worker = WorkerState::Working(exclusion, started);
+                needed -= 1;
+            }
+        }
+        for _ in 0..needed {
+            let worker_index = free_slot(workers);
+            if workers.iter().any(|worker| worker.is_supervisor()) {
+                let (recv, state) = WorkerState::available();
+                workers[worker_index] = state;
+                added.push((worker_index, Some(recv)));
+            } else {
+                workers[worker_index] = WorkerState::Supervisor;
+                added.push((worker_index, None));
+            }
+        }
+    } else {
+        let mut excess = active - thread_num;
+        for worker in workers.iter_mut() {
+            if excess == 0 {
+                break;
+            }
+            if let WorkerState::Available(_) = worker {
+                *worker = WorkerState::Stopped; // dropping the sender disconnects the available worker
+                excess -= 1;
+            }
+        }
+        for worker in workers.iter_mut() {
+            if excess == 0 {
+                break;
+            }
+            if let WorkerState::Working(exclusion, started) = *worker {
+                *worker = WorkerState::Retiring(exclusion, started);
+                excess -= 1;
+            }
+        }
+    }
+    added
+}
+
+/// The index of a stopped worker whose slot can be reused, or a new slot
+fn free_slot<J: Job>(workers: &mut Vec<WorkerState<J>>) -> usize {
+    workers
+        .iter()
+        .position(|worker| matches!(worker, WorkerState::Stopped))
+        .unwrap_or_else(|| {
+            workers.push(WorkerState::Stopped);
+            workers.len() - 1
+        })
+}
+
 #[derive(Debug)]
 enum PostJobTransition<J> {
     BecomeSupervisor,
     BecomeAvailable(crossbeam_channel::Receiver<J>),
     KeepWorking(J),
+    /// The pool has shrunk, so this worker exits
+    Retire,
 }
 
 #[derive(Debug)]
 enum WorkerState<J: Job> {
     Supervisor,
-    Working(J::Exclusion),
+    /// Executing a job with this exclusion, since the `Instant`
+    Working(J::Exclusion, Instant),
+    /// Working, but the worker will exit once the job is complete as the pool has shrunk
+    Retiring(J::Exclusion, Instant),
     Available(crossbeam_channel::Sender<J>),
+    /// The runner has exited
+    Stopped,
 }
 
 impl<J: Job> WorkerState<J> {
@@ -314,9 +1080,27 @@ impl<J: Job> WorkerState<J> {
         (recv, Self::Available(send))
     }
 
+    fn working(exclusion: J::Exclusion, started: Instant) -> Self {
+        Self::Working(exclusion, started)
+    }
+
+    fn status(&self) -> WorkerStatus<J::Exclusion> {
+        match self {
+            Self::Supervisor => WorkerStatus::Supervisor,
+            Self::Working(exclusion, started) | Self::Retiring(exclusion, started) => {
+                WorkerStatus::Working {
+                    exclusion: *exclusion,
+                    started: *started,
+                }
+            }
+            Self::Available(_) => WorkerStatus::Available,
+            Self::Stopped => WorkerStatus::Stopped,
+        }
+    }
+
     /// if worker is working, returns the exclusion, otherwise `None`
     fn exclusion(&self) -> Option<J::Exclusion> {
-        if let Self::Working(exclusion) = self {
+        if let Self::Working(exclusion, _) | Self::Retiring(exclusion, _) = self {
             Some(*exclusion)
         } else {
             None
@@ -324,7 +1108,12 @@ impl<J: Job> WorkerState<J> {
     }
 
     fn is_working(&self) -> bool {
-        matches!(self, Self::Working(_))
+        matches!(self, Self::Working(..) | Self::Retiring(..))
+    }
+
+    /// whether the worker is running and will continue to
+    fn is_active(&self) -> bool {
+        !matches!(self, Self::Retiring(..) | Self::Stopped)
     }
 
     fn is_supervisor(&self) -> bool {
@@ -334,7 +1123,11 @@ impl<J: Job> WorkerState<J> {
 
 #[cfg(test)]
 mod test {
-    use crate::{source::util::may_be_taken::VecSkipIter, Job, NoExclusion};
+    use crate::{
+        clock::{SystemClock, TestClock},
+        source::util::may_be_taken::VecSkipIter,
+        Job, JobRunner, NoExclusion,
+    };
 
     use super::*;
 
@@ -373,16 +1166,38 @@ mod test {
         fn execute(self) {}
     }
 
+    #[derive(Debug)]
+    struct DeadlineJob(char, Option<Instant>);
+
+    impl Job for DeadlineJob {
+        type Exclusion = NoExclusion;
+
+        fn exclusion(&self) -> Self::Exclusion {
+            NoExclusion
+        }
+
+        type Priority = ();
+
+        fn priority(&self) -> Self::Priority {}
+
+        fn deadline(&self) -> Option<Instant> {
+            self.1
+        }
+
+        fn execute(self) {}
+    }
+
     /// if a job completes and there is another supervisor, this worker becomes available
     #[test]
     fn working_to_available() {
         let state = RunnerState::<ExcludedJob> {
             workers: Arc::new(Mutex::new(vec![
-                WorkerState::Working(1),
+                WorkerState::working(1, Instant::now()),
                 WorkerState::Supervisor,
             ])),
             worker_index: 0,
             concurrency_limit: Arc::new(|()| None),
+            clock: Arc::new(SystemClock),
         };
         let job_recv = state.completed_job(PriorityQueue::new(None).drain());
         assert!(matches!(job_recv, PostJobTransition::BecomeAvailable(_)));
@@ -395,11 +1210,12 @@ mod test {
     fn working_to_supervisor() {
         let state = RunnerState::<ExcludedJob> {
             workers: Arc::new(Mutex::new(vec![
-                WorkerState::Working(1),
-                WorkerState::Working(2),
+                WorkerState::working(1, Instant::now()),
+                WorkerState::working(2, Instant::now()),
             ])),
             worker_index: 0,
             concurrency_limit: Arc::new(|()| None),
+            clock: Arc::new(SystemClock),
         };
         let job_recv = state.completed_job(PriorityQueue::new(None).drain());
         assert!(matches!(job_recv, PostJobTransition::BecomeSupervisor));
@@ -412,11 +1228,12 @@ mod test {
     fn working_to_working() {
         let state = RunnerState::<ExcludedJob> {
             workers: Arc::new(Mutex::new(vec![
-                WorkerState::Working(1),
-                WorkerState::Working(2),
+                WorkerState::working(1, Instant::now()),
+                WorkerState::working(2, Instant::now()),
             ])),
             worker_index: 0,
             concurrency_limit: Arc::new(|()| None),
+            clock: Arc::new(SystemClock),
         };
         let mut queue = PriorityQueue::new(None);
         queue.enqueue(ExcludedJob(3));
@@ -427,20 +1244,41 @@ mod test {
             job_recv
         );
         let workers = state.workers.lock();
-        assert!(workers[0].is_working());
+        assert_eq!(workers[0].exclusion(), Some(3));
         assert!(queue.is_empty());
     }
 
+    /// the time a worker started its job is told by the runner's clock
+    #[test]
+    fn working_started_by_clock() {
+        let clock = TestClock::new();
+        clock.advance(Duration::from_secs(60));
+        let state = RunnerState::<ExcludedJob> {
+            workers: Arc::new(Mutex::new(vec![WorkerState::working(1, Instant::now())])),
+            worker_index: 0,
+            concurrency_limit: Arc::new(|()| None),
+            clock: Arc::new(clock.clone()),
+        };
+        let mut queue = PriorityQueue::new(None);
+        queue.enqueue(ExcludedJob(2));
+        state.completed_job(queue.drain());
+        assert!(matches!(
+            state.workers.lock()[0].status(),
+            WorkerStatus::Working { exclusion: 2, started } if started == clock.now()
+        ));
+    }
+
     /// if a job completes and there is another job, but it is excluded, another job is not taken
     #[test]
     fn working_to_supervisor_excluded() {
         let state = RunnerState::<ExcludedJob> {
             workers: Arc::new(Mutex::new(vec![
-                WorkerState::Working(1),
-                WorkerState::Working(2),
+                WorkerState::working(1, Instant::now()),
+                WorkerState::working(2, Instant::now()),
             ])),
             worker_index: 0,
             concurrency_limit: Arc::new(|()| None),
+            clock: Arc::new(SystemClock),
         };
         let mut queue = PriorityQueue::new(None);
         queue.enqueue(ExcludedJob(1));
@@ -456,11 +1294,12 @@ mod test {
     fn working_to_supervisor_throttled() {
         let state = RunnerState::<PrioritisedJob> {
             workers: Arc::new(Mutex::new(vec![
-                WorkerState::Working(NoExclusion),
-                WorkerState::Working(NoExclusion),
+                WorkerState::working(NoExclusion, Instant::now()),
+                WorkerState::working(NoExclusion, Instant::now()),
             ])),
             worker_index: 0,
-            concurrency_limit: Arc::new(|num| Some(num)),
+            concurrency_limit: Arc::new(Some),
+            clock: Arc::new(SystemClock),
         };
         let mut queue = PriorityQueue::new(None);
         queue.enqueue(PrioritisedJob(1));
@@ -482,6 +1321,7 @@ mod test {
             ])),
             worker_index: 0,
             concurrency_limit: Arc::new(|()| None),
+            clock: Arc::new(SystemClock),
         };
         let mut jobs = vec![ExcludedJob(1)];
         assert!(state.assign_jobs(VecSkipIter::new(&mut jobs)).is_none());
@@ -497,10 +1337,11 @@ mod test {
         let state = RunnerState::<ExcludedJob> {
             workers: Arc::new(Mutex::new(vec![
                 WorkerState::Supervisor,
-                WorkerState::Working(1),
+                WorkerState::working(1, Instant::now()),
             ])),
             worker_index: 0,
             concurrency_limit: Arc::new(|()| None),
+            clock: Arc::new(SystemClock),
         };
         assert!(state
             .assign_jobs(VecSkipIter::new(&mut vec![ExcludedJob(2)]))
@@ -517,11 +1358,12 @@ mod test {
         let state = RunnerState::<ExcludedJob> {
             workers: Arc::new(Mutex::new(vec![
                 WorkerState::Supervisor,
-                WorkerState::Working(1),
+                WorkerState::working(1, Instant::now()),
                 WorkerState::Available(send),
             ])),
             worker_index: 0,
             concurrency_limit: Arc::new(|()| None),
+            clock: Arc::new(SystemClock),
         };
         let mut jobs = vec![ExcludedJob(1)];
         assert!(state.assign_jobs(VecSkipIter::new(&mut jobs)).is_none());
@@ -547,6 +1389,7 @@ mod test {
             ])),
             worker_index: 0,
             concurrency_limit: Arc::new(|()| None),
+            clock: Arc::new(SystemClock),
         };
         let mut jobs = vec![ExcludedJob(1), ExcludedJob(1)];
         assert!(state.assign_jobs(VecSkipIter::new(&mut jobs)).is_none());
@@ -567,10 +1410,11 @@ mod test {
         let state = RunnerState::<PrioritisedJob> {
             workers: Arc::new(Mutex::new(vec![
                 WorkerState::Supervisor,
-                WorkerState::Working(NoExclusion),
+                WorkerState::working(NoExclusion, Instant::now()),
             ])),
             worker_index: 0,
-            concurrency_limit: Arc::new(|priority| Some(priority)),
+            concurrency_limit: Arc::new(Some),
+            clock: Arc::new(SystemClock),
         };
         let mut jobs = vec![PrioritisedJob(1)];
         assert!(state.assign_jobs(VecSkipIter::new(&mut jobs)).is_none());
@@ -588,10 +1432,11 @@ mod test {
         let state = RunnerState::<PrioritisedJob> {
             workers: Arc::new(Mutex::new(vec![
                 WorkerState::Supervisor,
-                WorkerState::Working(NoExclusion),
+                WorkerState::working(NoExclusion, Instant::now()),
             ])),
             worker_index: 0,
-            concurrency_limit: Arc::new(|priority| Some(priority)),
+            concurrency_limit: Arc::new(Some),
+            clock: Arc::new(SystemClock),
         };
         assert!(state
             .assign_jobs(VecSkipIter::new(&mut vec![PrioritisedJob(2)]))
@@ -610,11 +1455,12 @@ mod test {
         let state = RunnerState::<PrioritisedJob> {
             workers: Arc::new(Mutex::new(vec![
                 WorkerState::Supervisor,
-                WorkerState::Working(NoExclusion),
+                WorkerState::working(NoExclusion, Instant::now()),
                 WorkerState::Available(send),
             ])),
             worker_index: 0,
-            concurrency_limit: Arc::new(|priority| Some(priority)),
+            concurrency_limit: Arc::new(Some),
+            clock: Arc::new(SystemClock),
         };
         let mut jobs = vec![PrioritisedJob(2), PrioritisedJob(2)];
         assert!(state.assign_jobs(VecSkipIter::new(&mut jobs)).is_none());
@@ -635,12 +1481,136 @@ mod test {
         let state = RunnerState::<PrioritisedJob> {
             workers: Arc::new(Mutex::new(vec![
                 WorkerState::Supervisor,
-                WorkerState::Working(NoExclusion),
+                WorkerState::working(NoExclusion, Instant::now()),
             ])),
             worker_index: 0,
-            concurrency_limit: Arc::new(|priority| Some(priority)),
+            concurrency_limit: Arc::new(Some),
+            clock: Arc::new(SystemClock),
         };
         assert!(state.assign_jobs(VecSkipIter::new(&mut jobs)).is_some());
         assert_eq!(jobs.len(), 1);
     }
+
+    /// a retiring worker exits once its job is completed
+    #[test]
+    fn retiring_to_stopped() {
+        let state = RunnerState::<ExcludedJob> {
+            workers: Arc::new(Mutex::new(vec![
+                WorkerState::Retiring(1, Instant::now()),
+                WorkerState::Supervisor,
+            ])),
+            worker_index: 0,
+            concurrency_limit: Arc::new(|()| None),
+            clock: Arc::new(SystemClock),
+        };
+        let mut queue = PriorityQueue::new(None);
+        queue.enqueue(ExcludedJob(3));
+        let job_recv = state.completed_job(queue.drain());
+        assert!(matches!(job_recv, PostJobTransition::Retire));
+        let workers = state.workers.lock();
+        assert!(matches!(workers[0], WorkerState::Stopped));
+        assert!(!queue.is_empty());
+    }
+
+    /// growing reactivates retiring workers, then reuses stopped slots, then adds more. New workers are available, unless there is no supervisor
+    #[test]
+    fn resize_grow() {
+        let mut workers = vec![
+            WorkerState::<ExcludedJob>::Retiring(1, Instant::now()),
+            WorkerState::Stopped,
+            WorkerState::working(2, Instant::now()),
+        ];
+        let added = resize_workers(&mut workers, 4);
+        assert!(matches!(added[..], [(1, None), (3, Some(_))]));
+        assert!(matches!(workers[0], WorkerState::Working(1, _)));
+        assert!(workers[1].is_supervisor());
+        assert!(matches!(workers[3], WorkerState::Available(_)));
+    }
+
+    /// shrinking removes available workers first, then retires working ones, but keeps the supervisor
+    #[test]
+    fn resize_shrink() {
+        let (_recv, available) = WorkerState::available();
+        let mut workers = vec![
+            WorkerState::<ExcludedJob>::working(1, Instant::now()),
+            WorkerState::Supervisor,
+            available,
+            WorkerState::working(2, Instant::now()),
+        ];
+        assert!(resize_workers(&mut workers, 1).is_empty());
+        assert!(matches!(workers[0], WorkerState::Retiring(1, _)));
+        assert!(workers[1].is_supervisor());
+        assert!(matches!(workers[2], WorkerState::Stopped));
+        assert!(matches!(workers[3], WorkerState::Retiring(2, _)));
+    }
+
+    /// with room for another worker, the supervisor gives its job to a new worker and remains the supervisor
+    #[test]
+    fn hand_off_to_new_worker() {
+        let state = RunnerState::<ExcludedJob> {
+            workers: Arc::new(Mutex::new(vec![
+                WorkerState::working(1, Instant::now()),
+                WorkerState::Stopped,
+            ])),
+            worker_index: 0,
+            concurrency_limit: Arc::new(|()| None),
+            clock: Arc::new(SystemClock),
+        };
+        assert_eq!(state.hand_off(2), Some(1));
+        let workers = state.workers.lock();
+        assert!(workers[0].is_supervisor());
+        assert_eq!(workers[1].exclusion(), Some(1));
+    }
+
+    /// with no room for another worker, the supervisor keeps its job
+    #[test]
+    fn hand_off_at_max() {
+        let state = RunnerState::<ExcludedJob> {
+            workers: Arc::new(Mutex::new(vec![
+                WorkerState::working(1, Instant::now()),
+                WorkerState::working(2, Instant::now()),
+            ])),
+            worker_index: 0,
+            concurrency_limit: Arc::new(|()| None),
+            clock: Arc::new(SystemClock),
+        };
+        assert_eq!(state.hand_off(2), None);
+        assert_eq!(state.workers.lock()[0].exclusion(), Some(1));
+    }
+
+    /// idle workers only retire while there are more than the minimum
+    #[test]
+    fn retire_idle_above_min() {
+        let (_recv, available) = WorkerState::available();
+        let state = RunnerState::<ExcludedJob> {
+            workers: Arc::new(Mutex::new(vec![available, WorkerState::Supervisor])),
+            worker_index: 0,
+            concurrency_limit: Arc::new(|()| None),
+            clock: Arc::new(SystemClock),
+        };
+        assert!(!state.retire_idle(2));
+        assert!(state.retire_idle(1));
+        assert!(matches!(state.workers.lock()[0], WorkerState::Stopped));
+    }
+
+    /// jobs whose deadline passes whilst they are queued are expired rather than executed, and counted
+    #[test]
+    fn expired() {
+        let expired = Arc::new(Mutex::new(String::new()));
+        let clock = TestClock::new();
+        let on_expired = expired.clone();
+        let mut runner = JobRunner::builder()
+            .clock(clock.clone())
+            .on_expired(move |job: DeadlineJob| on_expired.lock().push(job.0))
+            .build_manual(1);
+        runner.send(DeadlineJob('a', None)).unwrap();
+        let deadline = |secs| Some(clock.now() + Duration::from_secs(secs));
+        runner.send(DeadlineJob('b', deadline(30))).unwrap();
+        runner.send(DeadlineJob('c', deadline(90))).unwrap();
+        assert!(runner.step());
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(runner.run_until_idle(), 1);
+        assert_eq!(*expired.lock(), "b");
+        assert_eq!(runner.metrics().expired, 1);
+    }
 }
@@ -9,33 +9,54 @@ use std::{
     time::{Duration, Instant},
 };
 
-use crate::{MergeResult, Prioritised};
+use crate::{clock::Clock, handle::QueuedJob, Job};
 
-use self::util::{prioritized_mpsc, Drain, PriorityQueue};
+use self::{
+    delayed::Delayed,
+    schedule::{Schedule, Schedules},
+    store::{Persist, StateStore},
+    util::{
+        prioritized_mpsc::{self, Waker},
+        Drain, MergeFn, PriorityQueue,
+    },
+};
 
+pub(crate) mod cron;
+pub(crate) mod delayed;
+pub(crate) mod schedule;
+pub(crate) mod store;
 pub(crate) mod util;
 
 /// Contains a prioritised queue of jobs, adding recurring jobs which should always be scheduled with some interval
-pub(crate) struct SourceManager<J: Prioritised, R> {
-    queue: prioritized_mpsc::Receiver<J>,
-    recurring: Vec<R>,
+pub(crate) struct SourceManager<J: Job, R> {
+    queue: prioritized_mpsc::Receiver<QueuedJob<J>>,
+    recurring: Vec<Schedule<R>>,
+    /// recurring jobs which can be added and controlled whilst running
+    schedules: Arc<Schedules<R>>,
+    /// jobs which have been sent to be enqueued later
+    delayed: Arc<Delayed<J>>,
+    /// once closed, recurring jobs are no longer created
+    closed: bool,
+    clock: Arc<dyn Clock>,
 }
 
 #[cfg(test)]
-impl<J: Prioritised + Send + RecurrableJob + 'static> SourceManager<J, IntervalRecurringJob<J>> {
+impl<J: Job + RecurrableJob + 'static> SourceManager<J, IntervalRecurringJob<J>> {
     /// Set a job as recurring, the job will be enqueued every time `interval` passes since the last enqueue of a matching job
     fn set_recurring(&mut self, interval: Duration, last_enqueue: Instant, job: J) {
-        self.recurring.push(IntervalRecurringJob {
-            last_enqueue,
+        self.recurring.push(Schedule::new(IntervalRecurringJob::new(
             interval,
+            last_enqueue,
+            RecurringPolicy::default(),
             job,
-        });
+            self.clock.clone(),
+        )));
     }
 }
 
-impl<J: Prioritised, R> fmt::Debug for SourceManager<J, R>
+impl<J: Job, R> fmt::Debug for SourceManager<J, R>
 where
-    <J as Prioritised>::Priority: fmt::Debug,
+    J::Priority: fmt::Debug,
     J: fmt::Debug,
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -43,31 +64,45 @@ where
     }
 }
 
-impl<J: Prioritised + Send + 'static, R: RecurringJob<J>> SourceManager<J, R> {
+impl<J: Job + 'static, R: RecurringJob<J>> SourceManager<J, R> {
     #[cfg(test)]
     /// Create a new `(Sender, SourceManager<>)` pair
-    pub fn new() -> (crossbeam_channel::Sender<J>, SourceManager<J, R>) {
-        let (send, recv) = prioritized_mpsc::channel(None);
-        (
-            send,
-            SourceManager {
-                queue: recv,
-                recurring: vec![],
-            },
-        )
+    pub fn new() -> (prioritized_mpsc::Sender<QueuedJob<J>>, SourceManager<J, R>) {
+        Self::new_with_clock(Arc::new(crate::clock::SystemClock))
     }
 
-    /// Create a new `(Sender, SourceManager<>)` pair with the provided recurring jobs
+    #[cfg(test)]
+    /// Create a new `(Sender, SourceManager<>)` pair which schedules recurring jobs using `clock`
+    pub fn new_with_clock(
+        clock: Arc<dyn Clock>,
+    ) -> (prioritized_mpsc::Sender<QueuedJob<J>>, SourceManager<J, R>) {
+        Self::new_with_recurring(vec![], None, None, clock)
+    }
+
+    /// Create a new `(Sender, SourceManager<>)` pair with the provided recurring jobs, optionally limiting the number of jobs waiting to `capacity`. Recurring jobs are scheduled using `clock`
     pub fn new_with_recurring(
         recurring: Vec<R>,
-        merge_fn: Option<fn(J, &mut J) -> MergeResult<J>>,
-    ) -> (crossbeam_channel::Sender<J>, SourceManager<J, R>) {
-        let (send, recv) = prioritized_mpsc::channel(merge_fn);
+        merge_fn: Option<Box<MergeFn<QueuedJob<J>>>>,
+        capacity: Option<usize>,
+        clock: Arc<dyn Clock>,
+    ) -> (prioritized_mpsc::Sender<QueuedJob<J>>, SourceManager<J, R>) {
+        let (send, recv) = prioritized_mpsc::bounded(merge_fn, capacity);
+        let waker = recv.waker();
+        clock.on_advance(Box::new(move || waker.wake()));
+        let schedules = Schedules::new(recv.waker(), clock.clone());
+        for recurring in recurring {
+            schedules.add(recurring);
+        }
+        let delayed = Delayed::new(recv.waker());
         (
             send,
             SourceManager {
                 queue: recv,
-                recurring,
+                recurring: vec![],
+                schedules: Arc::new(schedules),
+                delayed: Arc::new(delayed),
+                closed: false,
+                clock,
             },
         )
     }
@@ -77,37 +112,84 @@ impl<J: Prioritised + Send + 'static, R: RecurringJob<J>> SourceManager<J, R> {
     /// Maximum wait duration would be the longest interval of all of the recurring jobs, or an arbitrary timeout. It could return immediately. It could return with no jobs. The caller should only iterate as many jobs as it can execute, the iterator should be dropped without iterating the rest.
     ///
     /// wait_for_new: if set, only returns immedaitely if there are new jobs inthe queue
-    pub fn get(&mut self, wait_for_new: bool) -> Drain<J, MutexGuard<'_, PriorityQueue<J>>> {
+    pub fn get(
+        &mut self,
+        wait_for_new: bool,
+    ) -> Drain<QueuedJob<J>, MutexGuard<'_, PriorityQueue<QueuedJob<J>>>> {
+        self.update_schedules();
         let timeout = self.queue_timeout();
+        self.get_within(timeout, wait_for_new)
+    }
+
+    /// Get the next batch of prioritised jobs like [`SourceManager::get`], but return straight away rather than waiting for jobs
+    pub fn try_get(&mut self) -> Drain<QueuedJob<J>, MutexGuard<'_, PriorityQueue<QueuedJob<J>>>> {
+        self.update_schedules();
+        self.get_within(Duration::ZERO, false)
+    }
+
+    /// Receive the jobs which have been sent, waiting up to `timeout` for them, then add any recurring jobs which are ready
+    fn get_within(
+        &mut self,
+        timeout: Duration,
+        wait_for_new: bool,
+    ) -> Drain<QueuedJob<J>, MutexGuard<'_, PriorityQueue<QueuedJob<J>>>> {
         let recurring = &mut self.recurring;
+        let waker = self.queue.waker();
         if timeout == Duration::ZERO {
             self.queue.process_queue_ready(|new_enqueue| {
-                for recurring in recurring.iter_mut() {
-                    recurring.job_enqueued(new_enqueue);
+                for schedule in recurring.iter_mut() {
+                    schedule.job_enqueued(new_enqueue, &waker);
                 }
             });
         } else {
             self.queue
                 .process_queue_timeout(timeout, wait_for_new, |new_enqueue| {
-                    for recurring in recurring.iter_mut() {
-                        recurring.job_enqueued(new_enqueue);
+                    for schedule in recurring.iter_mut() {
+                        schedule.job_enqueued(new_enqueue, &waker);
                     }
                 });
         }
-        for item in self.recurring.iter().flat_map(R::get).collect::<Vec<_>>() {
-            for recurring in &mut self.recurring {
-                recurring.job_enqueued(&item);
+        if self.closed {
+            return self.queue.drain();
+        }
+        for mut item in self.delayed.take_due(self.clock.now()) {
+            for schedule in &mut self.recurring {
+                schedule.job_enqueued(&mut item, &waker);
+            }
+            self.queue.enqueue(item);
+        }
+        // the schedules could have changed whilst waiting
+        self.update_schedules();
+        for item in self
+            .recurring
+            .iter()
+            .flat_map(Schedule::get)
+            .collect::<Vec<_>>()
+        {
+            let mut item = QueuedJob::new(item);
+            for schedule in &mut self.recurring {
+                schedule.job_enqueued(&mut item, &waker);
             }
             self.queue.enqueue(item);
         }
         self.queue.drain()
     }
 
-    /// get the timeout to wait for the queue based on the status of the recurring jobs
+    /// Pick up any schedules which have been added, remove any which have been cancelled and apply changes to the rest
+    fn update_schedules(&mut self) {
+        self.recurring.append(&mut self.schedules.take_added());
+        self.recurring.retain_mut(Schedule::update);
+    }
+
+    /// get the timeout to wait for the queue based on the status of the recurring jobs and when the delayed jobs are due
     fn queue_timeout(&mut self) -> Duration {
-        if let Some(poll_time) = self.soonest_recurring() {
+        let soonest = match (self.soonest_recurring(), self.soonest_delayed()) {
+            (Some(recurring), Some(delayed)) => Some(recurring.min(delayed)),
+            (recurring, delayed) => recurring.or(delayed),
+        };
+        if let Some(poll_time) = soonest {
             poll_time
-                .checked_duration_since(Instant::now())
+                .checked_duration_since(self.clock.now())
                 .unwrap_or(Duration::ZERO) // a recurring job is ready
         } else {
             Duration::from_secs(5) // there are no pollers so this is kinda abitrary
@@ -116,16 +198,63 @@ impl<J: Prioritised + Send + 'static, R: RecurringJob<J>> SourceManager<J, R> {
 
     /// The soonest instant when a recurring job would need to be created
     fn soonest_recurring(&self) -> Option<Instant> {
-        self.recurring.iter().map(R::max_sleep).min()
+        if self.closed {
+            return None;
+        }
+        self.recurring.iter().flat_map(Schedule::max_sleep).min()
+    }
+
+    /// When the soonest delayed job is due
+    fn soonest_delayed(&self) -> Option<Instant> {
+        if self.closed {
+            return None;
+        }
+        self.delayed.soonest()
+    }
+
+    /// Stop creating recurring jobs and enqueuing delayed jobs, jobs which are sent to the queue are still received
+    pub fn close(&mut self) {
+        self.closed = true;
+    }
+
+    /// Whether there are no jobs in the queue, or waiting to be added to it
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Closes the queue so that nothing more can be sent, and removes all the jobs waiting in it, in priority order, followed by the delayed jobs in the order they're due
+    pub fn take_all(&mut self) -> Vec<J> {
+        self.queue.close();
+        self.queue.process_queue_ready(|_| {});
+        let mut jobs: Vec<_> = self.queue.drain().map(QueuedJob::cancel).collect();
+        jobs.extend(self.delayed.take_all().into_iter().map(QueuedJob::cancel));
+        jobs
+    }
+
+    /// The jobs which have been sent to be enqueued later
+    pub fn delayed(&self) -> Arc<Delayed<J>> {
+        self.delayed.clone()
+    }
+
+    /// Create a [`Waker`] which can interrupt the wait in [`SourceManager::get()`]
+    pub fn waker(&self) -> Waker {
+        self.queue.waker()
+    }
+
+    /// The recurring jobs, which can be added to and controlled whilst running
+    pub fn schedules(&self) -> Arc<Schedules<R>> {
+        self.schedules.clone()
     }
 
     /// Gets access to the priority queue that this source uses, be careful with this `Mutex` as `get()` will also lock it.
-    pub fn queue(&self) -> Arc<Mutex<PriorityQueue<J>>> {
+    pub fn queue(&self) -> Arc<Mutex<PriorityQueue<QueuedJob<J>>>> {
         self.queue.queue()
     }
 }
 
-/// Defines how a job recurs
+/// Defines how a job recurs, add one to the runner with [`Builder::add_recurring`](crate::Builder::add_recurring)
+///
+/// Whilst waiting for jobs, the supervisor sleeps until the soonest [`RecurringJob::max_sleep`] of all the recurring jobs, then calls [`RecurringJob::get`] on each of them and enqueues the jobs they return. Every job which is enqueued, whether it was sent to the runner or returned by a recurring job, is passed to [`RecurringJob::job_enqueued`] of each of the recurring jobs.
 pub trait RecurringJob<J> {
     /// Get the job if it is ready to recur
     fn get(&self) -> Option<J>;
@@ -133,6 +262,16 @@ pub trait RecurringJob<J> {
     fn job_enqueued(&mut self, job: &J);
     /// Returns the latest `Instant` that the caller could sleep until before it should call `get()` again
     fn max_sleep(&self) -> Instant;
+    /// Get the job straight away, whether or not it is ready to recur
+    fn trigger(&self) -> J;
+    /// Change the interval between recurrances, recurring jobs which don't have an interval ignore this
+    fn set_interval(&mut self, _interval: Duration) {}
+    /// Whether [`RecurringJob::job_completed`] should be called once this newly enqueued job has finished, for recurring jobs which recur some time after a matching job completes
+    fn awaits_completion(&self, _job: &J) -> bool {
+        false
+    }
+    /// Notifies the recurring job that a job which it awaited has finished at `completed`, this is also called if the job was cancelled or dropped without being executed
+    fn job_completed(&mut self, _completed: Instant) {}
 }
 
 impl<J> RecurringJob<J> for Box<dyn RecurringJob<J> + Send> {
@@ -140,10 +279,26 @@ impl<J> RecurringJob<J> for Box<dyn RecurringJob<J> + Send> {
         self.deref().get()
     }
 
+    fn trigger(&self) -> J {
+        self.deref().trigger()
+    }
+
+    fn set_interval(&mut self, interval: Duration) {
+        self.deref_mut().set_interval(interval)
+    }
+
     fn job_enqueued(&mut self, job: &J) {
         self.deref_mut().job_enqueued(job)
     }
 
+    fn awaits_completion(&self, job: &J) -> bool {
+        self.deref().awaits_completion(job)
+    }
+
+    fn job_completed(&mut self, completed: Instant) {
+        self.deref_mut().job_completed(completed)
+    }
+
     fn max_sleep(&self) -> Instant {
         self.deref().max_sleep()
     }
@@ -153,38 +308,366 @@ impl<J> RecurringJob<J> for Box<dyn RecurringJob<J> + Send> {
 pub trait RecurrableJob: Clone {
     /// When a job matching a `Recurrablejob` is scheduled, this resets the recurrance interval
     fn matches(&self, other: &Self) -> bool;
+
+    /// Called on each clone which is enqueued because the job recurred, with the instant that it was scheduled for. With [`MissedRunPolicy::RunAll`], this tells each of the jobs which of the missed runs it stands for
+    fn scheduled_for(&mut self, _scheduled: Instant) {}
+}
+
+/// What the interval of a recurring job is measured from, see [`RecurringPolicy::anchor`]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum RecurrenceAnchor {
+    /// The job recurs on regular ticks, every `interval` after the first anchor, however long the jobs take and whenever matching jobs are enqueued. What happens when ticks are missed depends on the [`MissedRunPolicy`]
+    FixedRate,
+    /// The job recurs once `interval` has passed since a matching job was enqueued
+    #[default]
+    SinceEnqueued,
+    /// The job recurs once `interval` has passed since a matching job completed, so there will only be one matching job in the runner at a time
+    SinceCompleted,
+}
+
+/// What to do when several ticks of a [`RecurrenceAnchor::FixedRate`] job have passed without it being enqueued, for example because every worker was busy or the process was suspended. With the other anchors the interval is measured from the last run, so runs can't be missed
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum MissedRunPolicy {
+    /// The missed runs are collapsed into a single run, which stands for the earliest of them
+    #[default]
+    RunOnce,
+    /// The job is enqueued once for each of the missed runs, up to this many of the most recent ones
+    RunAll(usize),
+    /// The missed runs are skipped, the job is only enqueued for the most recent tick
+    Skip,
+}
+
+/// A random delay added to each recurrence of a job, so that processes which were started at the same time don't all enqueue it at the same moment, see [`RecurringPolicy::jitter`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Jitter {
+    /// Up to this percentage of the interval, percentages over 100 are treated as 100
+    Percent(u8),
+    /// Between these bounds
+    Between(Duration, Duration),
+}
+
+impl Jitter {
+    /// pick a random delay for a job recurring at `interval`
+    fn pick(self, interval: Duration) -> Duration {
+        // the range in nanoseconds, which can't overflow as a u128
+        let (min, range) = match self {
+            Jitter::Percent(percent) => (
+                Duration::ZERO,
+                interval.as_nanos() * u128::from(percent.min(100)) / 100,
+            ),
+            Jitter::Between(min, max) => (min, max.saturating_sub(min).as_nanos()),
+        };
+        let range = range.min(u64::MAX.into()) as u64;
+        min + Duration::from_nanos(fastrand::u64(0..=range))
+    }
+}
+
+/// How a job recurs at an interval, see [`Builder::set_recurring_policy`](crate::Builder::set_recurring_policy)
+#[derive(Debug, Clone, Default)]
+pub struct RecurringPolicy {
+    anchor: RecurrenceAnchor,
+    singleton: bool,
+    missed: MissedRunPolicy,
+    jitter: Option<Jitter>,
+    first_run: Option<Duration>,
+    persist: Option<Persist>,
+}
+
+impl RecurringPolicy {
+    /// What the interval is measured from, the default is [`RecurrenceAnchor::SinceEnqueued`]
+    pub fn anchor(mut self, anchor: RecurrenceAnchor) -> Self {
+        self.anchor = anchor;
+        self
+    }
+
+    /// If set, the job isn't enqueued whilst a matching job is in the queue or being executed, so that there's never more than one of them in the runner at a time
+    pub fn singleton(mut self, singleton: bool) -> Self {
+        self.singleton = singleton;
+        self
+    }
+
+    /// What to do when runs are missed, the default is [`MissedRunPolicy::RunOnce`]
+    pub fn missed_runs(mut self, missed: MissedRunPolicy) -> Self {
+        self.missed = missed;
+        self
+    }
+
+    /// Add a random delay to each recurrence, by default there is none
+    pub fn jitter(mut self, jitter: Jitter) -> Self {
+        self.jitter = Some(jitter);
+        self
+    }
+
+    /// The job is first enqueued once `delay` has passed since it was set as recurring, rather than once the interval has passed
+    pub fn first_run_after(mut self, delay: Duration) -> Self {
+        self.first_run = Some(delay);
+        self
+    }
+
+    /// The job is first enqueued as soon as the runner starts, rather than once the interval has passed
+    pub fn run_immediately(self) -> Self {
+        self.first_run_after(Duration::ZERO)
+    }
+
+    /// Save when the job last ran to `store` under `name`, and carry on from there when it is next set as recurring with this name, rather than from when it was set. This overrides the first run
+    pub fn persist(mut self, name: impl Into<String>, store: Arc<dyn StateStore>) -> Self {
+        self.persist = Some(Persist::new(name.into(), store));
+        self
+    }
+
+    fn pick_jitter(&self, interval: Duration) -> Duration {
+        self.jitter
+            .map_or(Duration::ZERO, |jitter| jitter.pick(interval))
+    }
+}
+
+/// When a job recurs at an interval, according to the [`RecurringPolicy`]
+pub(crate) struct Recurrence {
+    /// the instant which the interval is measured from
+    last: Instant,
+    interval: Duration,
+    policy: RecurringPolicy,
+    /// matching jobs which have been enqueued and are yet to complete, if the policy awaits their completion
+    in_flight: usize,
+    /// random delay added to the next recurrence
+    jitter: Duration,
+    /// when the job should first be enqueued, if the policy sets it
+    first: Option<Instant>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Recurrence {
+    pub fn new(
+        interval: Duration,
+        last_enqueue: Instant,
+        policy: RecurringPolicy,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        let mut first = policy.first_run.map(|delay| clock.now() + delay);
+        let mut last = last_enqueue;
+        if let Some(saved) = policy
+            .persist
+            .as_ref()
+            .and_then(|persist| persist.load(&*clock))
+        {
+            first = None;
+            last = saved;
+        }
+        Self {
+            last,
+            interval,
+            jitter: policy.pick_jitter(interval),
+            policy,
+            in_flight: 0,
+            first,
+            clock,
+        }
+    }
+
+    /// save `last` if the policy persists it
+    fn save(&self) {
+        if let Some(persist) = &self.policy.persist {
+            persist.save(self.last, &*self.clock);
+        }
+    }
+
+    /// The instant which the next job stands for, if it should be enqueued now
+    fn due(&self) -> Option<Instant> {
+        if self.in_flight > 0 {
+            return None;
+        }
+        let now = self.clock.now();
+        if let Some(first) = self.first {
+            (now >= first).then_some(first)
+        } else if self.policy.anchor == RecurrenceAnchor::FixedRate && !self.interval.is_zero() {
+            let passed = self.ticks_passed(now);
+            (passed > 0).then(|| self.tick(self.next_tick(passed)))
+        } else {
+            (now > self.last + self.interval + self.jitter).then_some(self.last + self.interval)
+        }
+    }
+
+    /// How many ticks have passed since `last`, each one is delayed by the jitter
+    fn ticks_passed(&self, now: Instant) -> u128 {
+        now.saturating_duration_since(self.last + self.jitter)
+            .as_nanos()
+            / self.interval.as_nanos()
+    }
+
+    /// The instant of the `n`th tick after `last`
+    fn tick(&self, n: u128) -> Instant {
+        self.last + Duration::from_nanos((self.interval.as_nanos() * n) as u64)
+    }
+
+    /// Which of the ticks which have passed the next job stands for, according to the [`MissedRunPolicy`]
+    fn next_tick(&self, passed: u128) -> u128 {
+        match self.policy.missed {
+            MissedRunPolicy::RunOnce => 1,
+            MissedRunPolicy::RunAll(max) => passed.saturating_sub(max.max(1) as u128) + 1,
+            MissedRunPolicy::Skip => passed,
+        }
+    }
+
+    /// a matching job has been enqueued
+    fn enqueued(&mut self) {
+        let now = self.clock.now();
+        if self.awaits_completion() {
+            self.in_flight += 1;
+        }
+        if let Some(first) = self.first {
+            if self.policy.anchor == RecurrenceAnchor::FixedRate && now < first {
+                return; // not on a tick
+            }
+            self.first = None;
+            // for a fixed rate, the ticks follow on from the first run
+            self.last = first;
+        }
+        match self.policy.anchor {
+            RecurrenceAnchor::FixedRate => {
+                if self.interval.is_zero() {
+                    self.last = now;
+                } else {
+                    let passed = self.ticks_passed(now);
+                    if passed > 0 {
+                        self.last = match self.policy.missed {
+                            MissedRunPolicy::RunAll(_) => self.tick(self.next_tick(passed)),
+                            // any other missed runs are collapsed or skipped
+                            MissedRunPolicy::RunOnce | MissedRunPolicy::Skip => self.tick(passed),
+                        };
+                    }
+                }
+            }
+            RecurrenceAnchor::SinceEnqueued | RecurrenceAnchor::SinceCompleted => self.last = now,
+        }
+        self.jitter = self.policy.pick_jitter(self.interval);
+        self.save();
+    }
+
+    fn awaits_completion(&self) -> bool {
+        self.policy.singleton || self.policy.anchor == RecurrenceAnchor::SinceCompleted
+    }
+
+    fn completed(&mut self, completed: Instant) {
+        self.in_flight = self.in_flight.saturating_sub(1);
+        if self.policy.anchor == RecurrenceAnchor::SinceCompleted {
+            self.last = self.last.max(completed);
+            self.save();
+        }
+    }
+
+    fn max_sleep(&self) -> Instant {
+        if self.in_flight > 0 {
+            // woken by the completion, so this is kinda arbitrary
+            self.clock.now() + self.interval
+        } else if let Some(first) = self.first {
+            first
+        } else {
+            self.last + self.interval + self.jitter
+        }
+    }
 }
 
 /// Recurring job which works by updating the last time a job was enqueued reenqueueing after some interval
 pub struct IntervalRecurringJob<J: RecurrableJob> {
-    pub(crate) last_enqueue: Instant,
-    pub(crate) interval: Duration,
+    pub(crate) recurrence: Recurrence,
     pub(crate) job: J,
 }
 
+impl<J: RecurrableJob> IntervalRecurringJob<J> {
+    pub(crate) fn new(
+        interval: Duration,
+        last_enqueue: Instant,
+        policy: RecurringPolicy,
+        job: J,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        Self {
+            recurrence: Recurrence::new(interval, last_enqueue, policy, clock),
+            job,
+        }
+    }
+}
+
 impl<J: RecurrableJob> RecurringJob<J> for IntervalRecurringJob<J> {
     fn get(&self) -> Option<J> {
-        if Instant::now() > self.last_enqueue + self.interval {
-            Some(self.job.clone())
-        } else {
-            None
-        }
+        self.recurrence.due().map(|scheduled| {
+            let mut job = self.job.clone();
+            job.scheduled_for(scheduled);
+            job
+        })
     }
 
     fn job_enqueued(&mut self, job: &J) {
         if self.job.matches(job) {
-            self.last_enqueue = Instant::now();
+            self.recurrence.enqueued();
+        }
+    }
+
+    fn max_sleep(&self) -> Instant {
+        self.recurrence.max_sleep()
+    }
+
+    fn trigger(&self) -> J {
+        self.job.clone()
+    }
+
+    fn set_interval(&mut self, interval: Duration) {
+        self.recurrence.interval = interval;
+    }
+
+    fn awaits_completion(&self, job: &J) -> bool {
+        self.recurrence.awaits_completion() && self.job.matches(job)
+    }
+
+    fn job_completed(&mut self, completed: Instant) {
+        self.recurrence.completed(completed);
+    }
+}
+
+/// Recurring job which builds a fresh job with `factory` each time it recurs, rather than cloning one, jobs are matched with `matcher` rather than [`RecurrableJob::matches`]
+pub(crate) struct FactoryRecurringJob<J> {
+    pub(crate) recurrence: Recurrence,
+    pub(crate) matcher: Box<dyn Fn(&J) -> bool + Send>,
+    pub(crate) factory: Box<dyn Fn() -> J + Send>,
+}
+
+impl<J> RecurringJob<J> for FactoryRecurringJob<J> {
+    fn get(&self) -> Option<J> {
+        self.recurrence.due().map(|_| (self.factory)())
+    }
+
+    fn job_enqueued(&mut self, job: &J) {
+        if (self.matcher)(job) {
+            self.recurrence.enqueued();
         }
     }
 
     fn max_sleep(&self) -> Instant {
-        self.last_enqueue + self.interval
+        self.recurrence.max_sleep()
+    }
+
+    fn trigger(&self) -> J {
+        (self.factory)()
+    }
+
+    fn set_interval(&mut self, interval: Duration) {
+        self.recurrence.interval = interval;
+    }
+
+    fn awaits_completion(&self, job: &J) -> bool {
+        self.recurrence.awaits_completion() && (self.matcher)(job)
+    }
+
+    fn job_completed(&mut self, completed: Instant) {
+        self.recurrence.completed(completed);
     }
 }
 
 /// Just until the never type is stable, this represents that the job does not recur
+#[cfg(test)]
 enum NeverRecur {}
 
+#[cfg(test)]
 impl<J> RecurringJob<J> for NeverRecur {
     fn get(&self) -> Option<J> {
         unreachable!()
@@ -197,29 +680,45 @@ impl<J> RecurringJob<J> for NeverRecur {
     fn max_sleep(&self) -> Instant {
         unreachable!()
     }
+
+    fn trigger(&self) -> J {
+        unreachable!()
+    }
 }
 
 #[cfg(test)]
 mod test {
     use std::{
+        io,
         sync::{Arc, Barrier},
         thread,
-        time::Duration,
+        time::{Duration, SystemTime},
     };
 
-    use crate::Prioritised;
+    use crate::{
+        clock::{SystemClock, TestClock},
+        NoExclusion,
+    };
 
     use super::*;
 
     #[derive(Debug, Clone, PartialEq)]
     struct Tester(u8);
 
-    impl Prioritised for Tester {
+    impl Job for Tester {
+        type Exclusion = NoExclusion;
+
+        fn exclusion(&self) -> Self::Exclusion {
+            NoExclusion
+        }
+
         type Priority = u8;
 
         fn priority(&self) -> Self::Priority {
             self.0
         }
+
+        fn execute(self) {}
     }
 
     impl RecurrableJob for Tester {
@@ -231,11 +730,14 @@ mod test {
     #[test]
     fn priority_queue() {
         let (send, mut manager) = SourceManager::<_, NeverRecur>::new();
-        send.send(Tester(2)).unwrap();
-        send.send(Tester(3)).unwrap();
-        send.send(Tester(1)).unwrap();
+        send.send(QueuedJob::new(Tester(2))).unwrap();
+        send.send(QueuedJob::new(Tester(3))).unwrap();
+        send.send(QueuedJob::new(Tester(1))).unwrap();
         assert_eq!(
-            manager.get(false).collect::<Vec<_>>(),
+            manager
+                .get(false)
+                .map(QueuedJob::into_inner)
+                .collect::<Vec<_>>(),
             vec![Tester(3), Tester(2), Tester(1)]
         )
     }
@@ -249,7 +751,10 @@ mod test {
         manager.set_recurring(Duration::from_secs(1), one_min_ago, Tester(3));
         let before = Instant::now();
         assert_eq!(
-            manager.get(false).collect::<Vec<_>>(),
+            manager
+                .get(false)
+                .map(QueuedJob::into_inner)
+                .collect::<Vec<_>>(),
             vec![Tester(3), Tester(2), Tester(1)]
         );
         assert!(Instant::now().duration_since(before) < Duration::from_millis(1));
@@ -263,12 +768,18 @@ mod test {
         manager.set_recurring(Duration::from_millis(1), one_min_ago, Tester(2));
         manager.set_recurring(Duration::from_millis(1), one_min_ago, Tester(3));
         assert_eq!(
-            manager.get(false).collect::<Vec<_>>(),
+            manager
+                .get(false)
+                .map(QueuedJob::into_inner)
+                .collect::<Vec<_>>(),
             vec![Tester(3), Tester(2), Tester(1)]
         );
         let before = Instant::now();
         assert_eq!(
-            manager.get(false).collect::<Vec<_>>(),
+            manager
+                .get(false)
+                .map(QueuedJob::into_inner)
+                .collect::<Vec<_>>(),
             vec![Tester(3), Tester(2), Tester(1)]
         );
         assert!(
@@ -286,11 +797,18 @@ mod test {
         manager.set_recurring(Duration::from_millis(1), one_min_ago, Tester(2));
         manager.set_recurring(Duration::from_millis(1), one_min_ago, Tester(3));
         assert_eq!(
-            manager.get(false).take(1).collect::<Vec<_>>(),
+            manager
+                .get(false)
+                .take(1)
+                .map(QueuedJob::into_inner)
+                .collect::<Vec<_>>(),
             vec![Tester(3)]
         );
         assert_eq!(
-            manager.get(false).collect::<Vec<_>>(),
+            manager
+                .get(false)
+                .map(QueuedJob::into_inner)
+                .collect::<Vec<_>>(),
             vec![Tester(2), Tester(1)]
         );
     }
@@ -303,21 +821,68 @@ mod test {
         manager.set_recurring(Duration::from_millis(10), half_interval_ago, Tester(1));
         manager.set_recurring(Duration::from_millis(10), half_interval_ago, Tester(2));
         manager.set_recurring(Duration::from_millis(10), half_interval_ago, Tester(3));
-        send.send(Tester(2)).unwrap();
+        send.send(QueuedJob::new(Tester(2))).unwrap();
         assert_eq!(
-            manager.get(false).collect::<Vec<_>>(),
+            manager
+                .get(false)
+                .map(QueuedJob::into_inner)
+                .collect::<Vec<_>>(),
             vec![Tester(2)],
             "Wrong result after {:?}",
             Instant::now().duration_since(start)
         );
         let restart = Instant::now();
         assert_eq!(
-            manager.get(false).collect::<Vec<_>>(),
+            manager
+                .get(false)
+                .map(QueuedJob::into_inner)
+                .collect::<Vec<_>>(),
             vec![Tester(3), Tester(1)],
             "Wrong result after {:?}",
             Instant::now().duration_since(restart)
         );
-        assert_eq!(manager.get(false).collect::<Vec<_>>(), vec![Tester(2)]);
+        assert_eq!(
+            manager
+                .get(false)
+                .map(QueuedJob::into_inner)
+                .collect::<Vec<_>>(),
+            vec![Tester(2)]
+        );
+    }
+
+    #[test]
+    fn queued_resets_recurring_test_clock() {
+        let clock = TestClock::new();
+        let (send, mut manager) = SourceManager::new_with_clock(Arc::new(clock.clone()));
+        let start = clock.now();
+        manager.set_recurring(Duration::from_secs(10), start, Tester(1));
+        manager.set_recurring(Duration::from_secs(10), start, Tester(2));
+        manager.set_recurring(Duration::from_secs(10), start, Tester(3));
+        clock.advance(Duration::from_secs(5));
+        send.send(QueuedJob::new(Tester(2))).unwrap();
+        assert_eq!(
+            manager
+                .get(false)
+                .map(QueuedJob::into_inner)
+                .collect::<Vec<_>>(),
+            vec![Tester(2)]
+        );
+        clock.advance(Duration::from_secs(6));
+        assert_eq!(
+            manager
+                .get(false)
+                .map(QueuedJob::into_inner)
+                .collect::<Vec<_>>(),
+            vec![Tester(3), Tester(1)]
+        );
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(
+            manager
+                .get(false)
+                .map(QueuedJob::into_inner)
+                .collect::<Vec<_>>(),
+            vec![Tester(2)]
+        );
     }
 
     #[test]
@@ -326,9 +891,12 @@ mod test {
         let now = Instant::now();
         manager.set_recurring(Duration::from_millis(1), now, Tester(1));
         manager.set_recurring(Duration::from_millis(1), now, Tester(3));
-        send.send(Tester(2)).unwrap();
+        send.send(QueuedJob::new(Tester(2))).unwrap();
         assert_eq!(
-            manager.get(false).collect::<Vec<_>>(),
+            manager
+                .get(false)
+                .map(QueuedJob::into_inner)
+                .collect::<Vec<_>>(),
             vec![Tester(2)],
             "Wrong result after {:?}",
             Instant::now().duration_since(now)
@@ -341,9 +909,12 @@ mod test {
         let one_min_ago = Instant::now() - Duration::from_secs(60);
         manager.set_recurring(Duration::from_millis(1), one_min_ago, Tester(1));
         manager.set_recurring(Duration::from_millis(1), one_min_ago, Tester(3));
-        send.send(Tester(2)).unwrap();
+        send.send(QueuedJob::new(Tester(2))).unwrap();
         assert_eq!(
-            manager.get(false).collect::<Vec<_>>(),
+            manager
+                .get(false)
+                .map(QueuedJob::into_inner)
+                .collect::<Vec<_>>(),
             vec![Tester(3), Tester(2), Tester(1)]
         );
     }
@@ -360,14 +931,270 @@ mod test {
         thread::spawn(move || {
             b1.wait();
             thread::sleep(Duration::from_millis(5));
-            send.send(Tester(2)).unwrap()
+            send.send(QueuedJob::new(Tester(2))).unwrap()
         });
         b2.wait();
         let before = Instant::now();
         assert_eq!(
-            manager.get(false).collect::<Vec<_>>(),
+            manager
+                .get(false)
+                .map(QueuedJob::into_inner)
+                .collect::<Vec<_>>(),
             vec![Tester(3), Tester(2), Tester(1)]
         );
         assert!(Instant::now().duration_since(before) < Duration::from_millis(1));
     }
+
+    #[test]
+    fn paused_and_cancelled_not_recurring() {
+        let (_send, mut manager) = SourceManager::<_, IntervalRecurringJob<_>>::new();
+        let one_min_ago = Instant::now() - Duration::from_secs(60);
+        let schedules = manager.schedules();
+        let paused = schedules.add(IntervalRecurringJob::new(
+            Duration::from_millis(1),
+            one_min_ago,
+            RecurringPolicy::default(),
+            Tester(1),
+            Arc::new(SystemClock),
+        ));
+        let cancelled = schedules.add(IntervalRecurringJob::new(
+            Duration::from_millis(1),
+            one_min_ago,
+            RecurringPolicy::default(),
+            Tester(2),
+            Arc::new(SystemClock),
+        ));
+        schedules.add(IntervalRecurringJob::new(
+            Duration::from_secs(30),
+            one_min_ago,
+            RecurringPolicy::default(),
+            Tester(3),
+            Arc::new(SystemClock),
+        ));
+        paused.pause();
+        cancelled.cancel();
+        assert_eq!(
+            manager
+                .get(false)
+                .map(QueuedJob::into_inner)
+                .collect::<Vec<_>>(),
+            vec![Tester(3)]
+        );
+        assert_eq!(schedules.handles().len(), 2);
+        paused.resume();
+        assert_eq!(
+            manager
+                .get(false)
+                .map(QueuedJob::into_inner)
+                .collect::<Vec<_>>(),
+            vec![Tester(1)]
+        );
+    }
+
+    #[test]
+    fn triggered_recurs_immediately() {
+        let (_send, mut manager) = SourceManager::<_, IntervalRecurringJob<_>>::new();
+        let handle = manager.schedules().add(IntervalRecurringJob::new(
+            Duration::from_secs(60),
+            Instant::now(),
+            RecurringPolicy::default(),
+            Tester(1),
+            Arc::new(SystemClock),
+        ));
+        handle.pause();
+        handle.trigger_now();
+        let before = Instant::now();
+        assert_eq!(
+            manager
+                .get(false)
+                .map(QueuedJob::into_inner)
+                .collect::<Vec<_>>(),
+            vec![Tester(1)]
+        );
+        assert!(Instant::now().duration_since(before) < Duration::from_millis(1));
+    }
+
+    #[test]
+    fn fixed_rate_skips_to_latest_tick() {
+        let first = Instant::now() - Duration::from_millis(25);
+        let mut recurrence = Recurrence::new(
+            Duration::from_millis(10),
+            first,
+            RecurringPolicy::default().anchor(RecurrenceAnchor::FixedRate),
+            Arc::new(SystemClock),
+        );
+        assert_eq!(recurrence.due(), Some(first + Duration::from_millis(10)));
+        recurrence.enqueued();
+        assert_eq!(recurrence.last, first + Duration::from_millis(20));
+        assert_eq!(recurrence.due(), None);
+        recurrence.enqueued(); // not on a tick, so doesn't affect it
+        assert_eq!(recurrence.last, first + Duration::from_millis(20));
+    }
+
+    #[test]
+    fn since_completed_awaits_completion() {
+        let (_send, mut manager) = SourceManager::<_, IntervalRecurringJob<_>>::new();
+        let one_min_ago = Instant::now() - Duration::from_secs(60);
+        manager.schedules().add(IntervalRecurringJob::new(
+            Duration::from_millis(1),
+            one_min_ago,
+            RecurringPolicy::default().anchor(RecurrenceAnchor::SinceCompleted),
+            Tester(1),
+            Arc::new(SystemClock),
+        ));
+        let running = manager.get(false).collect::<Vec<_>>();
+        assert_eq!(running.len(), 1);
+        assert!(manager.get(false).next().is_none());
+        drop(running);
+        thread::sleep(Duration::from_millis(2));
+        assert_eq!(
+            manager
+                .get(false)
+                .map(QueuedJob::into_inner)
+                .collect::<Vec<_>>(),
+            vec![Tester(1)]
+        );
+    }
+
+    #[test]
+    fn singleton_not_duplicated() {
+        let (send, mut manager) = SourceManager::<_, IntervalRecurringJob<_>>::new();
+        manager.schedules().add(IntervalRecurringJob::new(
+            Duration::from_millis(1),
+            Instant::now(),
+            RecurringPolicy::default().singleton(true),
+            Tester(1),
+            Arc::new(SystemClock),
+        ));
+        send.send(QueuedJob::new(Tester(1))).unwrap();
+        let running = manager.get(false).collect::<Vec<_>>();
+        assert_eq!(running.len(), 1);
+        thread::sleep(Duration::from_millis(2));
+        assert!(manager.get(false).next().is_none());
+        drop(running);
+        assert_eq!(
+            manager
+                .get(false)
+                .map(QueuedJob::into_inner)
+                .collect::<Vec<_>>(),
+            vec![Tester(1)]
+        );
+    }
+
+    #[test]
+    fn missed_runs() {
+        let first = Instant::now() - Duration::from_millis(55);
+        let recurrence = |missed| {
+            Recurrence::new(
+                Duration::from_millis(10),
+                first,
+                RecurringPolicy::default()
+                    .anchor(RecurrenceAnchor::FixedRate)
+                    .missed_runs(missed),
+                Arc::new(SystemClock),
+            )
+        };
+        let tick = |n: u64| first + Duration::from_millis(n * 10);
+
+        let mut run_all = recurrence(MissedRunPolicy::RunAll(2));
+        assert_eq!(run_all.due(), Some(tick(4)));
+        run_all.enqueued();
+        assert_eq!(run_all.due(), Some(tick(5)));
+        run_all.enqueued();
+        assert_eq!(run_all.due(), None);
+
+        let mut skip = recurrence(MissedRunPolicy::Skip);
+        assert_eq!(skip.due(), Some(tick(5)));
+        skip.enqueued();
+        assert_eq!(skip.due(), None);
+    }
+
+    #[test]
+    fn jitter_within_bounds() {
+        let (five, six) = (Duration::from_millis(5), Duration::from_millis(6));
+        for _ in 0..100 {
+            assert!(Jitter::Percent(10).pick(Duration::from_secs(10)) <= Duration::from_secs(1));
+            let jitter = Jitter::Between(five, six).pick(Duration::from_secs(10));
+            assert!(five <= jitter && jitter <= six, "{:?}", jitter);
+            assert!(Jitter::Percent(250).pick(Duration::from_secs(10)) <= Duration::from_secs(10));
+        }
+        // without overflowing
+        assert!(Jitter::Percent(100).pick(Duration::MAX) <= Duration::MAX);
+    }
+
+    #[test]
+    fn jitter_delays_recurrence() {
+        let last = Instant::now() - Duration::from_millis(12);
+        let five = Duration::from_millis(5);
+        let recurrence = Recurrence::new(
+            Duration::from_millis(10),
+            last,
+            RecurringPolicy::default().jitter(Jitter::Between(five, five)),
+            Arc::new(SystemClock),
+        );
+        assert_eq!(recurrence.due(), None);
+        assert_eq!(recurrence.max_sleep(), last + Duration::from_millis(15));
+    }
+
+    #[test]
+    fn first_run() {
+        let immediate = Recurrence::new(
+            Duration::from_secs(60),
+            Instant::now(),
+            RecurringPolicy::default().run_immediately(),
+            Arc::new(SystemClock),
+        );
+        assert!(immediate.due().is_some());
+        let mut delayed = Recurrence::new(
+            Duration::from_millis(10),
+            Instant::now() - Duration::from_secs(60),
+            RecurringPolicy::default().first_run_after(Duration::from_secs(60)),
+            Arc::new(SystemClock),
+        );
+        assert_eq!(delayed.due(), None);
+        assert_eq!(Some(delayed.max_sleep()), delayed.first);
+        delayed.first = Some(Instant::now());
+        assert!(delayed.due().is_some());
+        delayed.enqueued();
+        assert_eq!(delayed.due(), None);
+    }
+
+    #[test]
+    fn persisted_last_run() {
+        struct Memory(Mutex<Option<SystemTime>>);
+
+        impl StateStore for Memory {
+            fn load(&self, _name: &str) -> io::Result<Option<SystemTime>> {
+                Ok(*self.0.lock())
+            }
+
+            fn save(&self, _name: &str, last_run: SystemTime) -> io::Result<()> {
+                *self.0.lock() = Some(last_run);
+                Ok(())
+            }
+        }
+
+        let store = Arc::new(Memory(Mutex::new(None)));
+        let policy = RecurringPolicy::default()
+            .run_immediately()
+            .persist("job", store.clone());
+        let mut recurrence = Recurrence::new(
+            Duration::from_secs(60),
+            Instant::now(),
+            policy.clone(),
+            Arc::new(SystemClock),
+        );
+        assert!(recurrence.due().is_some());
+        recurrence.enqueued();
+        assert!(store.0.lock().is_some());
+        // after a restart it carries on from the last run, rather than running immediately
+        let restarted = Recurrence::new(
+            Duration::from_secs(60),
+            Instant::now(),
+            policy,
+            Arc::new(SystemClock),
+        );
+        assert_eq!(restarted.due(), None);
+        assert!(restarted.max_sleep() > Instant::now() + Duration::from_secs(59));
+    }
 }
@@ -0,0 +1,452 @@
+//! Recurring jobs scheduled by cron expressions, see [`CronRecurringJob`]
+
+use std::{
+    error::Error,
+    fmt,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant, UNIX_EPOCH},
+};
+
+use super::{RecurrableJob, RecurringJob};
+use crate::clock::Clock;
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// The furthest ahead to look for a matching time, long enough for a leap day to fall on any day of the week
+const SEARCH_DAYS: i64 = 29 * 366;
+
+/// Error parsing a cron expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronError(String);
+
+impl fmt::Display for CronError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid cron expression: {}", self.0)
+    }
+}
+
+impl Error for CronError {}
+
+/// The set of values allowed for one of the fields of the expression
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Field(u64);
+
+impl Field {
+    fn contains(&self, value: i64) -> bool {
+        self.0 & (1 << value) != 0
+    }
+
+    /// Parse a comma separated list of values, ranges and steps, eg. `1,5-10,*/15`. Names are matched case-insensitively and numbered from `min`. Fields with names are cyclic, so their ranges can wrap around past the last name
+    fn parse(
+        expr: &str,
+        name: &str,
+        min: u32,
+        max: u32,
+        names: &[&str],
+    ) -> Result<Self, CronError> {
+        let value = |value: &str| -> Result<u32, CronError> {
+            names
+                .iter()
+                .position(|name| name.eq_ignore_ascii_case(value))
+                .map(|idx| idx as u32 + min)
+                .or_else(|| value.parse().ok())
+                .filter(|value| (min..=max).contains(value))
+                .ok_or_else(|| CronError(format!("{:?} is not a valid {}", value, name)))
+        };
+        let mut field = 0;
+        for part in expr.split(',') {
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => (range, Some(step)),
+                None => (part, None),
+            };
+            let (start, end) = if range == "*" || range == "?" {
+                (min, max)
+            } else if let Some((start, end)) = range.split_once('-') {
+                (value(start)?, value(end)?)
+            } else if step.is_some() {
+                (value(range)?, max)
+            } else {
+                let value = value(range)?;
+                (value, value)
+            };
+            let step = match step {
+                Some(step) => step
+                    .parse()
+                    .ok()
+                    .filter(|step| *step > 0)
+                    .ok_or_else(|| CronError(format!("{:?} is not a valid step", step)))?,
+                None => 1,
+            };
+            let values: Vec<_> = if start <= end {
+                (start..=end).collect()
+            } else if !names.is_empty() {
+                // eg. `FRI-MON`, the cycle ends with the last name, as for days of the week 7 is only another name for Sunday
+                (start..min + names.len() as u32).chain(min..=end).collect()
+            } else {
+                return Err(CronError(format!(
+                    "{:?} is an empty range of {}",
+                    range, name
+                )));
+            };
+            for value in values.into_iter().step_by(step) {
+                field |= 1 << value;
+            }
+        }
+        Ok(Field(field))
+    }
+}
+
+/// A parsed cron expression, matching times in UTC, or with a fixed offset from UTC
+///
+/// Expressions have 5 fields, `minute hour day-of-month month day-of-week`, or 6 fields with seconds first. Each field can be `*`, a value, a range `a-b`, a step `*/n`, `a/n` or `a-b/n`, or a comma separated list of these. Months and days of the week can be given as names (`JAN`, `MON`), both 0 and 7 are Sunday, and their ranges can wrap around, such as `FRI-MON` or `NOV-FEB`. Like Vixie cron, if both the day of the month and the day of the week are restricted, a day matching either is matched, otherwise a day has to match both. A field starting with `*`, such as `*/2`, doesn't count as restricted. `@yearly`, `@monthly`, `@weekly`, `@daily` and `@hourly` are also supported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    seconds: Field,
+    minutes: Field,
+    hours: Field,
+    days_of_month: Field,
+    months: Field,
+    days_of_week: Field,
+    /// whether both the day fields are restricted, in which case a day matching either matches
+    either_day: bool,
+    /// seconds ahead of UTC
+    utc_offset: i64,
+}
+
+impl CronSchedule {
+    /// Parse a cron expression, to match times in UTC
+    pub fn parse(expr: &str) -> Result<Self, CronError> {
+        let expr = match expr.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            expr => expr,
+        };
+        let fields: Vec<_> = expr.split_whitespace().collect();
+        let (seconds, fields) = match fields.len() {
+            5 => (Field(1), &fields[..]),
+            6 => (Field::parse(fields[0], "second", 0, 59, &[])?, &fields[1..]),
+            len => return Err(CronError(format!("expected 5 or 6 fields, found {}", len))),
+        };
+        let mut days_of_week = Field::parse(
+            fields[4],
+            "day of the week",
+            0,
+            7,
+            &["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"],
+        )?;
+        if days_of_week.contains(7) {
+            days_of_week.0 = (days_of_week.0 | 1) & !(1 << 7);
+        }
+        // like Vixie cron, a field starting with `*`, such as `*/2`, doesn't count as restricted
+        let restricted = |field: &str| !field.starts_with('*') && field != "?";
+        Ok(Self {
+            seconds,
+            minutes: Field::parse(fields[0], "minute", 0, 59, &[])?,
+            hours: Field::parse(fields[1], "hour", 0, 23, &[])?,
+            days_of_month: Field::parse(fields[2], "day of the month", 1, 31, &[])?,
+            months: Field::parse(
+                fields[3],
+                "month",
+                1,
+                12,
+                &[
+                    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV",
+                    "DEC",
+                ],
+            )?,
+            days_of_week,
+            either_day: restricted(fields[2]) && restricted(fields[4]),
+            utc_offset: 0,
+        })
+    }
+
+    /// Match times with a fixed offset from UTC, eg. `3600` for UTC+01:00
+    pub fn with_utc_offset(mut self, offset_seconds: i32) -> Self {
+        self.utc_offset = offset_seconds.into();
+        self
+    }
+
+    /// The first matching time after `after`, as seconds since the unix epoch, `None` if nothing matches in the next few decades
+    fn next_after(&self, after: i64) -> Option<i64> {
+        // local time, in seconds since the epoch
+        let mut time = after + self.utc_offset + 1;
+        let limit = time + SEARCH_DAYS * SECONDS_PER_DAY;
+        while time < limit {
+            let days = time.div_euclid(SECONDS_PER_DAY);
+            let seconds = time.rem_euclid(SECONDS_PER_DAY);
+            let (year, month, day) = civil_from_days(days);
+            if !self.months.contains(month) {
+                let (year, month) = if month == 12 {
+                    (year + 1, 1)
+                } else {
+                    (year, month + 1)
+                };
+                time = days_from_civil(year, month, 1) * SECONDS_PER_DAY;
+                continue;
+            }
+            if !self.day_matches(day, (days + 4).rem_euclid(7)) {
+                time = (days + 1) * SECONDS_PER_DAY;
+                continue;
+            }
+            let (hour, minute, second) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+            if !self.hours.contains(hour) {
+                time = days * SECONDS_PER_DAY + (hour + 1) * 3600;
+            } else if !self.minutes.contains(minute) {
+                time = days * SECONDS_PER_DAY + hour * 3600 + (minute + 1) * 60;
+            } else if !self.seconds.contains(second) {
+                time += 1;
+            } else {
+                return Some(time - self.utc_offset);
+            }
+        }
+        None
+    }
+
+    /// `weekday` is 0 for Sunday
+    fn day_matches(&self, day: i64, weekday: i64) -> bool {
+        let day_of_month = self.days_of_month.contains(day);
+        let day_of_week = self.days_of_week.contains(weekday);
+        if self.either_day {
+            day_of_month || day_of_week
+        } else {
+            day_of_month && day_of_week
+        }
+    }
+
+    /// The next matching time after now according to `clock`, `None` if there isn't one
+    fn next_instant(&self, clock: &dyn Clock) -> Option<Instant> {
+        let (now, since_epoch) = (clock.now(), clock.system_time().duration_since(UNIX_EPOCH));
+        let since_epoch = since_epoch.unwrap_or_default();
+        let next = self.next_after(since_epoch.as_secs() as i64)?;
+        let until = Duration::from_secs(next as u64).checked_sub(since_epoch)?;
+        Some(now + until)
+    }
+}
+
+impl FromStr for CronSchedule {
+    type Err = CronError;
+
+    fn from_str(expr: &str) -> Result<Self, Self::Err> {
+        Self::parse(expr)
+    }
+}
+
+/// Days since the unix epoch of a date in the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// `(year, month, day)` of a number of days since the unix epoch
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Recurring job which is enqueued at the times matching a cron expression, see [`Builder::set_recurring_cron`](crate::Builder::set_recurring_cron)
+pub struct CronRecurringJob<J: RecurrableJob> {
+    schedule: CronSchedule,
+    /// `None` if the schedule will never match
+    next: Option<Instant>,
+    job: J,
+    clock: Arc<dyn Clock>,
+}
+
+impl<J: RecurrableJob> CronRecurringJob<J> {
+    /// Enqueue `job` at each of the times matching `schedule`, according to the time of day of `clock`
+    pub fn new(schedule: CronSchedule, job: J, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            next: schedule.next_instant(&*clock),
+            schedule,
+            job,
+            clock,
+        }
+    }
+}
+
+impl<J: RecurrableJob> RecurringJob<J> for CronRecurringJob<J> {
+    fn get(&self) -> Option<J> {
+        match self.next {
+            Some(next) if self.clock.now() >= next => Some(self.job.clone()),
+            _ => None,
+        }
+    }
+
+    fn job_enqueued(&mut self, job: &J) {
+        // the times are fixed by the schedule, so only this job firing moves it onto the next time
+        if self.get().is_some() && self.job.matches(job) {
+            self.next = self.schedule.next_instant(&*self.clock);
+        }
+    }
+
+    fn max_sleep(&self) -> Instant {
+        self.next
+            .unwrap_or_else(|| self.clock.now() + Duration::from_secs(SECONDS_PER_DAY as u64))
+    }
+
+    fn trigger(&self) -> J {
+        self.job.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::TestClock;
+
+    use super::*;
+
+    /// 2021-01-01T00:00:00Z, a Friday
+    const NEW_YEAR_2021: i64 = 1609459200;
+    const HOUR: i64 = 3600;
+    const DAY: i64 = 24 * HOUR;
+
+    fn next(expr: &str, after: i64) -> Option<i64> {
+        CronSchedule::parse(expr).unwrap().next_after(after)
+    }
+
+    #[test]
+    fn civil_round_trip() {
+        assert_eq!(days_from_civil(2021, 1, 1), NEW_YEAR_2021 / DAY);
+        for days in [-800_000, -1, 0, 59, 11_016, 18_628, 800_000] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+    }
+
+    #[test]
+    fn daily() {
+        assert_eq!(
+            next("0 2 * * *", NEW_YEAR_2021 + 3 * HOUR),
+            Some(NEW_YEAR_2021 + DAY + 2 * HOUR)
+        );
+        assert_eq!(next("@daily", NEW_YEAR_2021), Some(NEW_YEAR_2021 + DAY));
+    }
+
+    #[test]
+    fn steps_lists_and_seconds() {
+        assert_eq!(
+            next("*/15 * * * *", NEW_YEAR_2021 + 60),
+            Some(NEW_YEAR_2021 + 15 * 60)
+        );
+        assert_eq!(
+            next("5,50 * * * *", NEW_YEAR_2021 + 6 * 60),
+            Some(NEW_YEAR_2021 + 50 * 60)
+        );
+        assert_eq!(
+            next("30 * * * * *", NEW_YEAR_2021),
+            Some(NEW_YEAR_2021 + 30)
+        );
+    }
+
+    #[test]
+    fn days_of_week_and_month() {
+        // wrapping ranges, from the following saturday
+        assert_eq!(
+            next("0 0 * * FRI-MON", NEW_YEAR_2021),
+            Some(NEW_YEAR_2021 + DAY)
+        );
+        assert_eq!(
+            next("0 0 * * SAT-SUN/2", NEW_YEAR_2021 + DAY),
+            Some(NEW_YEAR_2021 + 8 * DAY)
+        );
+        assert_eq!(
+            next("0 0 1 NOV-FEB *", NEW_YEAR_2021),
+            Some(days_from_civil(2021, 2, 1) * DAY)
+        );
+        // the following monday
+        assert_eq!(
+            next("0 0 * * MON", NEW_YEAR_2021),
+            Some(NEW_YEAR_2021 + 3 * DAY)
+        );
+        assert_eq!(
+            next("0 0 * * 7", NEW_YEAR_2021),
+            Some(NEW_YEAR_2021 + 2 * DAY)
+        );
+        // either the 2nd or a monday
+        assert_eq!(next("0 0 2 * 1", NEW_YEAR_2021), Some(NEW_YEAR_2021 + DAY));
+        // a step from `*` isn't a restriction, so only the mondays on odd days
+        assert_eq!(
+            next("0 0 */2 * MON", NEW_YEAR_2021),
+            Some(NEW_YEAR_2021 + 10 * DAY)
+        );
+        assert_eq!(
+            next("0 0 1 MAR *", NEW_YEAR_2021),
+            Some(days_from_civil(2021, 3, 1) * DAY)
+        );
+        assert_eq!(
+            next("0 0 29 2 *", NEW_YEAR_2021),
+            Some(days_from_civil(2024, 2, 29) * DAY)
+        );
+        assert_eq!(next("0 0 30 2 *", NEW_YEAR_2021), None);
+    }
+
+    #[test]
+    fn utc_offset() {
+        let schedule = CronSchedule::parse("0 2 * * *")
+            .unwrap()
+            .with_utc_offset(3600);
+        assert_eq!(
+            schedule.next_after(NEW_YEAR_2021),
+            Some(NEW_YEAR_2021 + HOUR)
+        );
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Tester(char);
+
+    impl RecurrableJob for Tester {
+        fn matches(&self, other: &Self) -> bool {
+            self == other
+        }
+    }
+
+    #[test]
+    fn recurs_by_clock() {
+        let clock = TestClock::at(UNIX_EPOCH + Duration::from_secs(NEW_YEAR_2021 as u64));
+        let mut recurring = CronRecurringJob::new(
+            CronSchedule::parse("0 2 * * *").unwrap(),
+            Tester('a'),
+            Arc::new(clock.clone()),
+        );
+        let start = clock.now();
+        assert_eq!(
+            recurring.max_sleep(),
+            start + Duration::from_secs(2 * HOUR as u64)
+        );
+        assert_eq!(recurring.get(), None);
+        clock.advance(Duration::from_secs(2 * HOUR as u64));
+        assert_eq!(recurring.get(), Some(Tester('a')));
+        recurring.job_enqueued(&Tester('a'));
+        assert_eq!(recurring.get(), None);
+        assert_eq!(
+            recurring.max_sleep(),
+            start + Duration::from_secs((DAY + 2 * HOUR) as u64)
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(CronSchedule::parse("* * * *").is_err());
+        assert!(CronSchedule::parse("60 * * * *").is_err());
+        assert!(CronSchedule::parse("* * * FOO *").is_err());
+        assert!(CronSchedule::parse("*/0 * * * *").is_err());
+        assert!(CronSchedule::parse("5-1 * * * *").is_err());
+    }
+}
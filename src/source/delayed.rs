@@ -0,0 +1,80 @@
+//! Jobs which have been sent to be enqueued later, see [`JobRunner::send_at`](crate::JobRunner::send_at)
+
+use parking_lot::Mutex;
+use std::{collections::BTreeMap, mem, time::Instant};
+
+use crate::handle::{JobId, QueuedJob};
+
+use super::util::prioritized_mpsc::Waker;
+
+/// Jobs waiting to be enqueued, in the order they're due, shared between the [`JobRunner`](crate::JobRunner) and the [`SourceManager`](super::SourceManager)
+pub(crate) struct Delayed<J> {
+    /// ordered by when they're due, then by the order they were sent
+    jobs: Mutex<BTreeMap<(Instant, JobId), QueuedJob<J>>>,
+    waker: Waker,
+}
+
+impl<J> Delayed<J> {
+    pub fn new(waker: Waker) -> Self {
+        Self {
+            jobs: Mutex::default(),
+            waker,
+        }
+    }
+
+    /// Add a job to be enqueued once `due` has passed, waking the supervisor so that it waits no longer than that
+    pub fn add(&self, due: Instant, job: QueuedJob<J>) {
+        self.jobs.lock().insert((due, job.id), job);
+        self.waker.wake();
+    }
+
+    /// When the soonest job is due, if there are any
+    pub fn soonest(&self) -> Option<Instant> {
+        self.jobs.lock().keys().next().map(|(due, _)| *due)
+    }
+
+    /// Remove the jobs which are due at `now`, in the order they're due
+    pub fn take_due(&self, now: Instant) -> Vec<QueuedJob<J>> {
+        let mut jobs = self.jobs.lock();
+        let later = jobs.split_off(&(now, JobId::MAX));
+        mem::replace(&mut *jobs, later).into_values().collect()
+    }
+
+    /// Remove all the jobs, in the order they're due
+    pub fn take_all(&self) -> Vec<QueuedJob<J>> {
+        mem::take(&mut *self.jobs.lock()).into_values().collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::source::util::prioritized_mpsc;
+
+    use super::*;
+
+    fn job() -> QueuedJob<fn()> {
+        QueuedJob::new(|| {})
+    }
+
+    #[test]
+    fn due_in_order() {
+        let (_send, recv) = prioritized_mpsc::channel::<QueuedJob<fn()>>(None);
+        let delayed = Delayed::new(recv.waker());
+        let now = Instant::now();
+        delayed.add(now + Duration::from_secs(2), job());
+        let first = job();
+        let first_id = first.id;
+        delayed.add(now, first);
+        delayed.add(now + Duration::from_secs(1), job());
+        assert_eq!(delayed.soonest(), Some(now));
+
+        let due = delayed.take_due(now + Duration::from_secs(1));
+        assert_eq!(due.len(), 2);
+        assert_eq!(due[0].id, first_id);
+        assert_eq!(delayed.soonest(), Some(now + Duration::from_secs(2)));
+        assert_eq!(delayed.take_all().len(), 1);
+        assert_eq!(delayed.soonest(), None);
+    }
+}
@@ -0,0 +1,228 @@
+//! Control of recurring jobs whilst the runner is running, see [`RecurringHandle`]
+
+use parking_lot::Mutex;
+use std::{
+    fmt, mem,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{clock::Clock, handle::QueuedJob, Job};
+
+use super::{util::prioritized_mpsc::Waker, RecurringJob};
+
+/// Changes requested through a [`RecurringHandle`], applied by the [`SourceManager`](super::SourceManager) when it next checks the recurring jobs
+#[derive(Debug, Default)]
+struct ScheduleState {
+    paused: bool,
+    cancelled: bool,
+    /// the job should be enqueued straight away
+    triggered: bool,
+    /// a new interval which is yet to be applied
+    interval: Option<Duration>,
+}
+
+/// A recurring job, along with the state controlled by it's [`RecurringHandle`]
+pub(crate) struct Schedule<R> {
+    pub(crate) recurring: R,
+    state: Arc<Mutex<ScheduleState>>,
+    /// when each of the jobs awaited by the recurring job finished, yet to be passed to [`RecurringJob::job_completed`]
+    completed: Arc<Mutex<Vec<Instant>>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl<R> Schedule<R> {
+    /// A schedule with no handle
+    #[cfg(test)]
+    pub fn new(recurring: R) -> Self {
+        Self {
+            recurring,
+            state: Arc::default(),
+            completed: Arc::default(),
+            clock: Arc::new(crate::clock::SystemClock),
+        }
+    }
+
+    /// Notify the recurring job that `job` has been enqueued, if it awaits the job's completion the job is given a token to notify it
+    pub fn job_enqueued<J: Job>(&mut self, job: &mut QueuedJob<J>, waker: &Waker)
+    where
+        R: RecurringJob<J>,
+    {
+        self.recurring.job_enqueued(&job.job);
+        if self.recurring.awaits_completion(&job.job) {
+            job.on_completion(CompletionToken {
+                completed: self.completed.clone(),
+                waker: waker.clone(),
+                clock: self.clock.clone(),
+            });
+        }
+    }
+
+    /// Apply any changes to the interval and pass on any completions, returns `false` if the schedule has been cancelled
+    pub fn update<J>(&mut self) -> bool
+    where
+        R: RecurringJob<J>,
+    {
+        let mut state = self.state.lock();
+        if let Some(interval) = state.interval.take() {
+            self.recurring.set_interval(interval);
+        }
+        for completed in mem::take(&mut *self.completed.lock()) {
+            self.recurring.job_completed(completed);
+        }
+        !state.cancelled
+    }
+
+    /// Get the job if it has been triggered, or if it is ready to recur and the schedule isn't paused
+    pub fn get<J>(&self) -> Option<J>
+    where
+        R: RecurringJob<J>,
+    {
+        let mut state = self.state.lock();
+        if mem::take(&mut state.triggered) {
+            Some(self.recurring.trigger())
+        } else if state.paused {
+            None
+        } else {
+            self.recurring.get()
+        }
+    }
+
+    /// The latest `Instant` that the caller could sleep until before it should call `get()` again, `None` if the schedule is paused
+    pub fn max_sleep<J>(&self) -> Option<Instant>
+    where
+        R: RecurringJob<J>,
+    {
+        let state = self.state.lock();
+        if state.triggered {
+            Some(self.clock.now())
+        } else if state.paused {
+            None
+        } else {
+            Some(self.recurring.max_sleep())
+        }
+    }
+}
+
+/// The recurring jobs which have been added to a runner, shared between the [`JobRunner`](crate::JobRunner) and the [`SourceManager`](super::SourceManager)
+pub(crate) struct Schedules<R> {
+    /// schedules which are yet to be picked up by the `SourceManager`
+    added: Mutex<Vec<Schedule<R>>>,
+    handles: Mutex<Vec<RecurringHandle>>,
+    waker: Waker,
+    clock: Arc<dyn Clock>,
+}
+
+impl<R> Schedules<R> {
+    pub fn new(waker: Waker, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            added: Mutex::default(),
+            handles: Mutex::default(),
+            waker,
+            clock,
+        }
+    }
+
+    /// Add a recurring job, it will be picked up the next time the recurring jobs are checked
+    pub fn add(&self, recurring: R) -> RecurringHandle {
+        let handle = RecurringHandle {
+            state: Arc::default(),
+            waker: self.waker.clone(),
+        };
+        self.added.lock().push(Schedule {
+            recurring,
+            state: handle.state.clone(),
+            completed: Arc::default(),
+            clock: self.clock.clone(),
+        });
+        self.handles.lock().push(handle.clone());
+        self.waker.wake();
+        handle
+    }
+
+    /// Take the schedules which have been added since this was last called
+    pub fn take_added(&self) -> Vec<Schedule<R>> {
+        mem::take(&mut *self.added.lock())
+    }
+
+    /// Handles on all of the schedules which haven't been cancelled
+    pub fn handles(&self) -> Vec<RecurringHandle> {
+        let mut handles = self.handles.lock();
+        handles.retain(|handle| !handle.is_cancelled());
+        handles.clone()
+    }
+}
+
+/// Held by a job which a recurring job awaits the completion of, records the completion when dropped and wakes the supervisor so that the recurring job can be rescheduled
+pub(crate) struct CompletionToken {
+    completed: Arc<Mutex<Vec<Instant>>>,
+    waker: Waker,
+    clock: Arc<dyn Clock>,
+}
+
+impl Drop for CompletionToken {
+    fn drop(&mut self) {
+        self.completed.lock().push(self.clock.now());
+        self.waker.wake();
+    }
+}
+
+/// Handle on a recurring job in a running [`JobRunner`](crate::JobRunner), see [`JobRunner::add_recurring`](crate::JobRunner::add_recurring) and [`JobRunner::recurring_handles`](crate::JobRunner::recurring_handles)
+#[derive(Clone)]
+pub struct RecurringHandle {
+    state: Arc<Mutex<ScheduleState>>,
+    waker: Waker,
+}
+
+impl RecurringHandle {
+    /// Stop the job from recurring until [`RecurringHandle::resume`] is called
+    pub fn pause(&self) {
+        self.update(|state| state.paused = true);
+    }
+
+    /// Let a paused job recur again
+    pub fn resume(&self) {
+        self.update(|state| state.paused = false);
+    }
+
+    /// Remove the recurring job from the runner, it won't recur again
+    pub fn cancel(&self) {
+        self.update(|state| state.cancelled = true);
+    }
+
+    /// Change the interval at which the job recurs, for recurring jobs which have an interval
+    pub fn set_interval(&self, interval: Duration) {
+        self.update(|state| state.interval = Some(interval));
+    }
+
+    /// Enqueue the job straight away, even if it is paused. This counts as the job being enqueued, so it resets the interval
+    pub fn trigger_now(&self) {
+        self.update(|state| state.triggered = true);
+    }
+
+    /// Whether the job has been paused
+    pub fn is_paused(&self) -> bool {
+        self.state.lock().paused
+    }
+
+    /// Whether the job has been cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.state.lock().cancelled
+    }
+
+    /// change the state then wake the supervisor so that it sees the change
+    fn update(&self, f: impl FnOnce(&mut ScheduleState)) {
+        f(&mut self.state.lock());
+        self.waker.wake();
+    }
+}
+
+impl fmt::Debug for RecurringHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.state.lock();
+        f.debug_struct("RecurringHandle")
+            .field("paused", &state.paused)
+            .field("cancelled", &state.cancelled)
+            .finish()
+    }
+}
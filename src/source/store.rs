@@ -0,0 +1,219 @@
+//! Persistence of when recurring jobs last ran, so that they keep to their schedule across restarts, see [`StateStore`]
+
+use parking_lot::Mutex;
+use std::{
+    collections::BTreeMap,
+    fmt, fs, io,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::clock::Clock;
+
+/// Stores when each named recurring job last ran, see [`RecurringPolicy::persist`](crate::RecurringPolicy::persist)
+///
+/// The state is saved by the supervisor each time a job is enqueued or completed, so this should be quick.
+pub trait StateStore: Send + Sync {
+    /// When the recurring job called `name` last ran, if it has been saved
+    fn load(&self, name: &str) -> io::Result<Option<SystemTime>>;
+    /// Save when the recurring job called `name` last ran
+    fn save(&self, name: &str, last_run: SystemTime) -> io::Result<()>;
+}
+
+/// [`StateStore`] which keeps the state of all the recurring jobs in a single file
+///
+/// Each line of the file is the milliseconds since the unix epoch, a space and then the name of the recurring job. The whole file is rewritten on each save.
+pub struct FileStateStore {
+    path: PathBuf,
+    state: Mutex<BTreeMap<String, SystemTime>>,
+}
+
+impl FileStateStore {
+    /// Open the store at `path`, reading any state which has already been saved there. The file is created when state is first saved
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let state = match fs::read_to_string(&path) {
+            Ok(contents) => parse(&contents)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(Self {
+            path,
+            state: Mutex::new(state),
+        })
+    }
+}
+
+fn parse(contents: &str) -> io::Result<BTreeMap<String, SystemTime>> {
+    contents
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let invalid = || {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid recurring job state {:?}", line),
+                )
+            };
+            let (millis, name) = line.split_once(' ').ok_or_else(invalid)?;
+            let millis = millis.parse().map_err(|_| invalid())?;
+            Ok((name.to_owned(), UNIX_EPOCH + Duration::from_millis(millis)))
+        })
+        .collect()
+}
+
+impl StateStore for FileStateStore {
+    fn load(&self, name: &str) -> io::Result<Option<SystemTime>> {
+        Ok(self.state.lock().get(name).copied())
+    }
+
+    fn save(&self, name: &str, last_run: SystemTime) -> io::Result<()> {
+        let mut state = self.state.lock();
+        state.insert(name.to_owned(), last_run);
+        let mut contents = String::new();
+        for (name, last_run) in state.iter() {
+            let millis = last_run
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis();
+            contents.push_str(&format!("{} {}\n", millis, name));
+        }
+        // write then rename, so that the file is never left half-written
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        fs::write(&tmp, contents)?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+impl fmt::Debug for FileStateStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileStateStore")
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+/// A recurring job's name along with the store it is persisted to
+#[derive(Clone)]
+pub(crate) struct Persist {
+    name: String,
+    store: Arc<dyn StateStore>,
+}
+
+impl Persist {
+    pub fn new(name: String, store: Arc<dyn StateStore>) -> Self {
+        Self { name, store }
+    }
+
+    /// When the job last ran according to the store, if it has been saved, measured by `clock`
+    pub fn load(&self, clock: &dyn Clock) -> Option<Instant> {
+        match self.store.load(&self.name) {
+            Ok(last_run) => last_run.map(|last_run| {
+                let now = clock.now();
+                let ago = clock
+                    .system_time()
+                    .duration_since(last_run)
+                    .unwrap_or_default();
+                now.checked_sub(ago).unwrap_or(now)
+            }),
+            Err(e) => {
+                log::warn!(
+                    "Failed to load state of recurring job {:?}: {}",
+                    self.name,
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    /// Save that the job last ran at `last`, measured by `clock`
+    pub fn save(&self, last: Instant, clock: &dyn Clock) {
+        let (now, time) = (clock.now(), clock.system_time());
+        let last_run = if last > now {
+            time + (last - now)
+        } else {
+            time.checked_sub(now - last).unwrap_or(UNIX_EPOCH)
+        };
+        if let Err(e) = self.store.save(&self.name, last_run) {
+            log::warn!(
+                "Failed to save state of recurring job {:?}: {}",
+                self.name,
+                e
+            );
+        }
+    }
+}
+
+impl fmt::Debug for Persist {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.name.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::TestClock;
+
+    use super::*;
+
+    #[test]
+    fn file_round_trip() {
+        let path = std::env::temp_dir().join(format!("gaffer-state-{}.state", std::process::id()));
+        // the temporary file written whilst saving doesn't replace the extension, so this is left alone
+        let other = path.with_extension("tmp");
+        fs::write(&other, "other").unwrap();
+        let store = FileStateStore::open(&path).unwrap();
+        assert_eq!(store.load("hourly sync").unwrap(), None);
+        let last_run = UNIX_EPOCH + Duration::from_millis(1_600_000_000_123);
+        store.save("hourly sync", last_run).unwrap();
+        store.save("billing", UNIX_EPOCH).unwrap();
+
+        let reopened = FileStateStore::open(&path).unwrap();
+        assert_eq!(reopened.load("hourly sync").unwrap(), Some(last_run));
+        assert_eq!(reopened.load("billing").unwrap(), Some(UNIX_EPOCH));
+        assert_eq!(fs::read_to_string(&other).unwrap(), "other");
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&other).unwrap();
+    }
+
+    #[derive(Default)]
+    struct MemoryStore(Mutex<Option<SystemTime>>);
+
+    impl StateStore for MemoryStore {
+        fn load(&self, _name: &str) -> io::Result<Option<SystemTime>> {
+            Ok(*self.0.lock())
+        }
+
+        fn save(&self, _name: &str, last_run: SystemTime) -> io::Result<()> {
+            *self.0.lock() = Some(last_run);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn persist_by_clock() {
+        let store = Arc::new(MemoryStore::default());
+        let persist = Persist::new("job".into(), store.clone());
+        let clock = TestClock::at(UNIX_EPOCH + Duration::from_secs(1000));
+        clock.advance(Duration::from_secs(60));
+        persist.save(clock.now(), &clock);
+        assert_eq!(
+            *store.0.lock(),
+            Some(UNIX_EPOCH + Duration::from_secs(1060))
+        );
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(
+            persist.load(&clock),
+            Some(clock.now() - Duration::from_secs(10))
+        );
+    }
+
+    #[test]
+    fn invalid_file() {
+        assert!(parse("123 ok\nnot a number\n").is_err());
+        assert_eq!(parse("\n5 a b\n").unwrap().len(), 1);
+    }
+}
@@ -1,37 +1,65 @@
+use parking_lot::Condvar;
 use std::{
     cmp::Reverse,
     collections::{BTreeMap, VecDeque},
     fmt,
     ops::DerefMut,
+    sync::Arc,
 };
 
 use crate::{MergeResult, Prioritised};
 
 use self::may_be_taken::SkipIterator;
 
+/// Function attempting to merge the first item into the second
+pub(crate) type MergeFn<T> = dyn Fn(T, &mut T) -> MergeResult<T> + Send;
+
 pub(crate) struct PriorityQueue<T: Prioritised> {
     map: BTreeMap<Reverse<T::Priority>, VecDeque<T>>,
-    merge_fn: Option<fn(T, &mut T) -> MergeResult<T>>,
+    merge_fn: Option<Box<MergeFn<T>>>,
+    /// notified whenever the queue gets shorter, so that senders waiting for capacity can wait on the queue's mutex
+    item_removed: Arc<Condvar>,
+    /// once closed, nothing more should be sent to the queue
+    closed: bool,
+    /// items which have been sent through a [`prioritized_mpsc::Sender`], but are yet to be received into the queue
+    sent: VecDeque<T>,
 }
 
 impl<T: Prioritised> Default for PriorityQueue<T> {
     fn default() -> Self {
-        PriorityQueue::new(None)
+        PriorityQueue::with_merge_fn(None)
     }
 }
 
-impl<T: Prioritised> PriorityQueue<T> {
+#[cfg(test)]
+impl<T: Prioritised + 'static> PriorityQueue<T> {
     pub fn new(merge_fn: Option<fn(T, &mut T) -> MergeResult<T>>) -> Self {
+        Self::with_merge_fn(merge_fn.map(|merge_fn| Box::new(merge_fn) as Box<MergeFn<T>>))
+    }
+}
+
+impl<T: Prioritised> PriorityQueue<T> {
+    pub fn with_merge_fn(merge_fn: Option<Box<MergeFn<T>>>) -> Self {
         Self {
             map: BTreeMap::new(),
             merge_fn,
+            item_removed: Arc::new(Condvar::new()),
+            closed: false,
+            sent: VecDeque::new(),
         }
     }
 
     /// Enqueues the item so that it will be iterated before any existing items in the queue with a lower priority and after any existing items with the same or higher priority.
     /// If `T` has a merge function in `T::ATTEMPT_MERGE_INTO`, the item will be merged into the highest priority existing item which merges successfully. The queue should maintain a state where everything that can be merged is merged, as long as the merge function is transitive in it's successes.
-    pub fn enqueue(&mut self, mut item: T) {
-        if let Some(attempt_merge) = self.merge_fn {
+    pub fn enqueue(&mut self, item: T) {
+        if let Err(item) = self.try_merge(item) {
+            self.enqueue_internal(item);
+        }
+    }
+
+    /// Attempts to merge the item into the highest priority existing item which merges successfully, returns the item if it couldn't be merged
+    pub fn try_merge(&mut self, mut item: T) -> Result<(), T> {
+        if let Some(attempt_merge) = &self.merge_fn {
             for (Reverse(priority), bucket) in &mut self.map {
                 // for now we iterate over the whole queue to look for merges, not the best solution
                 for (idx, existing) in bucket.iter_mut().enumerate() {
@@ -42,13 +70,15 @@ impl<T: Prioritised> PriorityQueue<T> {
                                 let item = bucket.remove(idx).unwrap();
                                 self.enqueue_internal(item);
                             }
-                            return;
+                            // the merged item might have come from outside the queue, but merged items could be counted elsewhere
+                            self.item_removed.notify_all();
+                            return Ok(());
                         }
                     }
                 }
             }
         }
-        self.enqueue_internal(item);
+        Err(item)
     }
 
     pub fn enqueue_internal(&mut self, item: T) -> &T {
@@ -60,6 +90,7 @@ impl<T: Prioritised> PriorityQueue<T> {
     pub fn dequeue(&mut self, mut idx: usize) -> Option<T> {
         for queue in self.map.values_mut() {
             if let Some(next) = queue.remove(idx) {
+                self.item_removed.notify_all();
                 return Some(next);
             } else {
                 idx -= queue.len();
@@ -94,12 +125,88 @@ impl<T: Prioritised> PriorityQueue<T> {
         }
     }
 
+    /// Removes the items for which `f` returns `false`, both from the queue and from those yet to be received into it, leaving the rest in order
+    pub fn retain(&mut self, mut f: impl FnMut(&T) -> bool) {
+        let len = self.len() + self.sent.len();
+        for queue in self.map.values_mut() {
+            queue.retain(|item| f(item));
+        }
+        self.sent.retain(|item| f(item));
+        if self.len() + self.sent.len() < len {
+            self.item_removed.notify_all();
+        }
+    }
+
+    /// Removes the items for which `f` returns `true`, returning them in order and leaving the rest in order
+    pub fn remove_where(&mut self, mut f: impl FnMut(&T) -> bool) -> Vec<T> {
+        let mut removed = vec![];
+        for queue in self.map.values_mut() {
+            let mut idx = 0;
+            while idx < queue.len() {
+                if f(&queue[idx]) {
+                    removed.extend(queue.remove(idx));
+                } else {
+                    idx += 1;
+                }
+            }
+        }
+        if !removed.is_empty() {
+            self.item_removed.notify_all();
+        }
+        removed
+    }
+
+    /// Applies `f` to every item, then enqueues them again so that they are ordered by their new priorities and any which can now be merged are merged
+    pub fn modify(&mut self, mut f: impl FnMut(&mut T)) {
+        let len = self.len();
+        let items: Vec<T> = self
+            .map
+            .values_mut()
+            .flat_map(|queue| queue.drain(..))
+            .collect();
+        for mut item in items {
+            f(&mut item);
+            self.enqueue(item);
+        }
+        if self.len() < len {
+            self.item_removed.notify_all();
+        }
+    }
+
+    /// Iterates the non-empty groups of items with the same priority, highest priority first
+    pub fn buckets(&self) -> impl Iterator<Item = (T::Priority, &VecDeque<T>)> {
+        self.map
+            .iter()
+            .filter(|(_, bucket)| !bucket.is_empty())
+            .map(|(Reverse(priority), bucket)| (*priority, bucket))
+    }
+
     pub fn is_empty(&self) -> bool {
         self.map.iter().all(|(_, queue)| queue.is_empty())
     }
 
     pub fn len(&self) -> usize {
-        self.map.iter().map(|(_, queue)| queue.len()).sum()
+        self.map.values().map(|queue| queue.len()).sum()
+    }
+
+    /// Condition variable which is notified whenever an item leaves the queue
+    pub fn item_removed(&self) -> Arc<Condvar> {
+        self.item_removed.clone()
+    }
+
+    /// The items which have been sent, but are yet to be received into the queue, in the order they were sent
+    pub fn sent(&self) -> &VecDeque<T> {
+        &self.sent
+    }
+
+    /// Mark the queue as closed, nothing more should be sent to it. Wakes anything waiting on [`PriorityQueue::item_removed`]
+    pub fn close(&mut self) {
+        self.closed = true;
+        self.item_removed.notify_all();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed
     }
 }
 
@@ -124,6 +231,14 @@ pub(crate) struct Drain<T: Prioritised, Q: DerefMut<Target = PriorityQueue<T>>>
     skip: usize,
 }
 
+impl<T: Prioritised, Q: DerefMut<Target = PriorityQueue<T>>> Drain<T, Q> {
+    /// Removes the items for which `f` returns `true` like [`PriorityQueue::remove_where`], this should be called before any items are skipped
+    pub fn remove_where(&mut self, f: impl FnMut(&T) -> bool) -> Vec<T> {
+        debug_assert_eq!(self.skip, 0);
+        self.queue.remove_where(f)
+    }
+}
+
 impl<T: Prioritised, Q: DerefMut<Target = PriorityQueue<T>>> Iterator for Drain<T, Q> {
     type Item = T;
 
@@ -325,7 +440,7 @@ mod test {
         let vals: String = queue.drain().map(|j| j.1).collect();
         assert_eq!(vals, "ac");
 
-        assert_eq!(queue.drain().map(|j| j.1).count(), 0);
+        assert_eq!(queue.drain().count(), 0);
     }
 
     #[derive(PartialEq, Eq, Debug)]
@@ -363,19 +478,171 @@ mod test {
         let vals: String = queue.drain().map(|j| j.1).collect();
         assert_eq!(vals, "abcdef");
     }
+
+    #[test]
+    fn modified_elements_are_reprioritised_and_merged() {
+        let mut queue = PriorityQueue::new(Some(merge));
+        queue.enqueue(MergableJob(2, 'a'));
+        queue.enqueue(MergableJob(1, 'b'));
+        queue.enqueue(MergableJob(1, 'c'));
+        queue.enqueue(MergableJob(1, 'd'));
+        queue.modify(|job| {
+            if job.1 == 'c' {
+                job.0 = 3;
+            } else if job.1 == 'd' {
+                job.1 = 'a';
+            }
+        });
+        let vals: Vec<_> = queue.drain().map(|j| (j.0, j.1)).collect();
+        assert_eq!(vals, vec![(3, 'c'), (2, 'a'), (1, 'b')]);
+    }
 }
 
 pub(crate) mod prioritized_mpsc {
+    use crossbeam_channel::{SendError, SendTimeoutError, TrySendError};
     use parking_lot::{Mutex, MutexGuard};
-    use std::{fmt, sync::Arc, thread, time::Duration};
+    use std::{
+        collections::VecDeque,
+        fmt,
+        sync::Arc,
+        time::{Duration, Instant},
+    };
 
-    use crate::{MergeResult, Prioritised};
+    use crate::Prioritised;
 
-    use super::PriorityQueue;
+    use super::{MergeFn, PriorityQueue};
 
+    /// Receiving side of the channel. Sent items wait in the queue's [`PriorityQueue::sent`] until they are received, which always happens with the queue locked, so that the senders can get at every item which is yet to be executed
     pub(crate) struct Receiver<T: Prioritised> {
         queue: Arc<Mutex<PriorityQueue<T>>>,
-        recv: crossbeam_channel::Receiver<T>,
+        wake_send: crossbeam_channel::Sender<()>,
+        wake_recv: crossbeam_channel::Receiver<()>,
+        /// signalled whilst there are sent items to receive, it disconnects once all the senders have been dropped
+        sent_signal: crossbeam_channel::Receiver<()>,
+        /// set once all the senders have been dropped
+        disconnected: bool,
+    }
+
+    /// Sending side of the channel. If the channel has a capacity, it is shared between the items in the queue and those which are yet to be received into it
+    pub(crate) struct Sender<T: Prioritised> {
+        queue: Arc<Mutex<PriorityQueue<T>>>,
+        /// signals the receiver that there are sent items to receive, only with the queue locked so that the signal is cleared along with the items
+        sent_signal: crossbeam_channel::Sender<()>,
+        capacity: Option<usize>,
+    }
+
+    impl<T: Prioritised> Clone for Sender<T> {
+        fn clone(&self) -> Self {
+            Self {
+                queue: self.queue.clone(),
+                sent_signal: self.sent_signal.clone(),
+                capacity: self.capacity,
+            }
+        }
+    }
+
+    impl<T: Prioritised> Sender<T> {
+        /// The queue which the items are received into
+        pub fn queue(&self) -> Arc<Mutex<PriorityQueue<T>>> {
+            self.queue.clone()
+        }
+
+        /// Removes the items for which `f` returns `false`, both from the queue and from those yet to be received into it
+        pub fn retain(&self, f: impl FnMut(&T) -> bool) {
+            self.queue.lock().retain(f);
+        }
+
+        /// Applies `f` to every item, both in the queue, which is then reordered and merged, and those yet to be received into it
+        pub fn modify(&self, mut f: impl FnMut(&mut T)) {
+            let mut queue = self.queue.lock();
+            queue.modify(&mut f);
+            queue.sent.iter_mut().for_each(f);
+        }
+
+        /// Calls `f` with the queue and the items which are yet to be received into it
+        pub fn inspect<R>(&self, f: impl FnOnce(&PriorityQueue<T>, &VecDeque<T>) -> R) -> R {
+            let queue = self.queue.lock();
+            f(&queue, queue.sent())
+        }
+
+        /// Add the item to those yet to be received and signal the receiver
+        fn push_sent(&self, queue: &mut PriorityQueue<T>, item: T) {
+            queue.sent.push_back(item);
+            // if it's full, the receiver has already been signalled
+            let _ = self.sent_signal.try_send(());
+        }
+
+        /// Send an item, blocking while the channel is full
+        pub fn send(&self, item: T) -> Result<(), SendError<T>> {
+            self.send_deadline(item, None).map_err(|err| match err {
+                SendTimeoutError::Timeout(item) | SendTimeoutError::Disconnected(item) => {
+                    SendError(item)
+                }
+            })
+        }
+
+        /// Send an item if the channel isn't full
+        pub fn try_send(&self, item: T) -> Result<(), TrySendError<T>> {
+            self.send_deadline(item, Some(Instant::now()))
+                .map_err(|err| match err {
+                    SendTimeoutError::Timeout(item) => TrySendError::Full(item),
+                    SendTimeoutError::Disconnected(item) => TrySendError::Disconnected(item),
+                })
+        }
+
+        /// Send an item, blocking up to `timeout` while the channel is full
+        pub fn send_timeout(&self, item: T, timeout: Duration) -> Result<(), SendTimeoutError<T>> {
+            self.send_deadline(item, Some(Instant::now() + timeout))
+        }
+
+        /// Send an item, blocking while the channel is full, until the `deadline`, if there is one. When full, the item can still be sent if it can be merged into the queue, as then it doesn't need any more capacity
+        fn send_deadline(
+            &self,
+            mut item: T,
+            deadline: Option<Instant>,
+        ) -> Result<(), SendTimeoutError<T>> {
+            let mut queue = self.queue.lock();
+            loop {
+                if queue.is_closed() {
+                    return Err(SendTimeoutError::Disconnected(item));
+                }
+                let full = matches!(self.capacity, Some(capacity) if queue.len() + queue.sent.len() >= capacity);
+                if !full {
+                    self.push_sent(&mut queue, item);
+                    return Ok(());
+                }
+                // merged where it is, the receiver never sees it, so it isn't passed to the callback
+                item = match queue.try_merge(item) {
+                    Ok(()) => return Ok(()),
+                    Err(item) => item,
+                };
+                let item_removed = queue.item_removed();
+                match deadline {
+                    Some(deadline) if Instant::now() >= deadline => {
+                        return Err(SendTimeoutError::Timeout(item))
+                    }
+                    Some(deadline) => {
+                        item_removed.wait_until(&mut queue, deadline);
+                    }
+                    None => item_removed.wait(&mut queue),
+                }
+            }
+        }
+    }
+
+    /// Interrupts a [`Receiver`] which is waiting in [`Receiver::process_queue_timeout`], so that it can recheck whatever it was waiting on
+    #[derive(Clone)]
+    pub(crate) struct Waker(crossbeam_channel::Sender<()>);
+
+    impl Waker {
+        /// Wake the receiver, if it isn't waiting, the next wait will return immediately. Returns `false` if the receiver has been dropped, so there is nothing left to wake
+        pub fn wake(&self) -> bool {
+            // if it's full, a wake is already pending
+            !matches!(
+                self.0.try_send(()),
+                Err(crossbeam_channel::TrySendError::Disconnected(()))
+            )
+        }
     }
 
     impl<T: Prioritised> fmt::Debug for Receiver<T>
@@ -390,39 +657,59 @@ pub(crate) mod prioritized_mpsc {
 
     impl<T: Prioritised> Receiver<T> {
         /// Processes things currently ready in the queue without blocking
-        pub fn process_queue_ready(&mut self, mut cb: impl FnMut(&T)) -> bool {
+        pub fn process_queue_ready(&mut self, mut cb: impl FnMut(&mut T)) -> bool {
             let mut has_new = false;
             let mut queue = self.queue.lock();
-            for item in self.recv.try_iter() {
-                cb(&item);
+            // the queue is locked, so the signal is cleared along with the items it was for
+            loop {
+                match self.sent_signal.try_recv() {
+                    Ok(()) => {}
+                    Err(crossbeam_channel::TryRecvError::Empty) => break,
+                    Err(crossbeam_channel::TryRecvError::Disconnected) => {
+                        self.disconnected = true;
+                        break;
+                    }
+                }
+            }
+            while let Some(mut item) = queue.sent.pop_front() {
+                cb(&mut item);
                 queue.enqueue(item);
                 has_new = true;
             }
             has_new
         }
 
-        /// Waits up to `timeout` for the first message, if none are currently available, if some are available (and `wait_for_new` is false) it returns immediately
+        /// Waits up to `timeout` for the first message, if none are currently available, if some are available (and `wait_for_new` is false) it returns immediately. Also returns early if woken by a [`Waker`] or if all the senders have been dropped
         pub fn process_queue_timeout(
             &mut self,
             timeout: Duration,
             wait_for_new: bool,
-            mut cb: impl FnMut(&T),
+            mut cb: impl FnMut(&mut T),
         ) {
             let has_new = self.process_queue_ready(&mut cb);
-            if !has_new && (wait_for_new || self.queue.lock().is_empty()) {
-                match self.recv.recv_timeout(timeout) {
-                    Ok(item) => {
-                        cb(&item);
-                        self.queue.lock().enqueue(item);
-                    }
-                    Err(crossbeam_channel::RecvTimeoutError::Timeout) => {}
-                    Err(crossbeam_channel::RecvTimeoutError::Disconnected) => {
-                        thread::sleep(timeout);
+            if !has_new && !self.disconnected && (wait_for_new || self.queue.lock().is_empty()) {
+                // only wait for the signal here, the items are received with the queue locked so that the senders never miss them whilst they're moved into the queue
+                crossbeam_channel::select! {
+                    recv(self.sent_signal) -> _ => {
+                        self.process_queue_ready(cb);
                     }
+                    recv(self.wake_recv) -> _ => {}
+                    default(timeout) => {}
                 }
             }
         }
 
+        /// whether there are no items, either in the queue or waiting to be processed into it
+        pub fn is_empty(&self) -> bool {
+            let queue = self.queue.lock();
+            queue.sent.is_empty() && queue.is_empty()
+        }
+
+        /// Create a [`Waker`] which can interrupt this receiver's waiting
+        pub fn waker(&self) -> Waker {
+            Waker(self.wake_send.clone())
+        }
+
         /// iterator over the currently available messages in priority order, any items not iterated when the iterator is dropped are left
         pub fn drain(&mut self) -> super::Drain<T, MutexGuard<'_, PriorityQueue<T>>> {
             PriorityQueue::drain_deref(self.queue.lock())
@@ -435,25 +722,63 @@ pub(crate) mod prioritized_mpsc {
         pub fn queue(&self) -> Arc<Mutex<PriorityQueue<T>>> {
             self.queue.clone()
         }
+
+        /// Close the queue so that nothing more can be sent, and drain everything from it
+        pub fn close(&mut self) {
+            self.queue.lock().close();
+        }
+    }
+
+    impl<T: Prioritised> Drop for Receiver<T> {
+        fn drop(&mut self) {
+            // nothing would ever receive what's sent from now on
+            self.close();
+        }
     }
 
     /// Produces an mpsc channel where, in the event that multiple jobs are already ready, they are produced in priority order
-    pub(crate) fn channel<T: Prioritised>(
-        merge_fn: Option<fn(T, &mut T) -> MergeResult<T>>,
-    ) -> (crossbeam_channel::Sender<T>, Receiver<T>) {
-        let (send, recv) = crossbeam_channel::unbounded();
+    #[cfg(test)]
+    pub(crate) fn channel<T: Prioritised + 'static>(
+        merge_fn: Option<fn(T, &mut T) -> crate::MergeResult<T>>,
+    ) -> (Sender<T>, Receiver<T>) {
+        bounded(
+            merge_fn.map(|merge_fn| Box::new(merge_fn) as Box<MergeFn<T>>),
+            None,
+        )
+    }
+
+    /// Produces an mpsc channel where items are received in priority order, which can hold at most `capacity` items, if provided
+    pub(crate) fn bounded<T: Prioritised>(
+        merge_fn: Option<Box<MergeFn<T>>>,
+        capacity: Option<usize>,
+    ) -> (Sender<T>, Receiver<T>) {
+        let (sent_send, sent_signal) = crossbeam_channel::bounded(1);
+        let (wake_send, wake_recv) = crossbeam_channel::bounded(1);
+        let queue = Arc::new(Mutex::new(PriorityQueue::with_merge_fn(merge_fn)));
         (
-            send,
+            Sender {
+                queue: queue.clone(),
+                sent_signal: sent_send,
+                capacity,
+            },
             Receiver {
-                queue: Arc::new(Mutex::new(PriorityQueue::new(merge_fn))),
-                recv,
+                queue,
+                wake_send,
+                wake_recv,
+                sent_signal,
+                disconnected: false,
             },
         )
     }
 
     #[cfg(test)]
     mod test {
-        use std::time::{Duration, Instant};
+        use std::{
+            sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+            time::{Duration, Instant},
+        };
+
+        use crate::MergeResult;
 
         use super::*;
 
@@ -464,7 +789,7 @@ pub(crate) mod prioritized_mpsc {
             type Priority = u8;
 
             fn priority(&self) -> Self::Priority {
-                self.0.into()
+                self.0
             }
         }
 
@@ -485,6 +810,165 @@ pub(crate) mod prioritized_mpsc {
             assert!(Instant::now().duration_since(instant) < Duration::from_millis(1));
         }
 
+        #[test]
+        fn woken_during_wait() {
+            let (_send, mut recv) = channel::<Tester>(None);
+            recv.waker().wake();
+            let instant = Instant::now();
+            recv.process_queue_timeout(Duration::from_secs(1), true, |_| {});
+            assert!(Instant::now().duration_since(instant) < Duration::from_millis(100));
+        }
+
+        #[test]
+        fn disconnected_returns_immediately() {
+            let (send, mut recv) = channel::<Tester>(None);
+            send.send(Tester(0)).unwrap();
+            drop(send);
+            recv.process_queue_timeout(Duration::from_secs(1), false, |_| {});
+            let instant = Instant::now();
+            recv.process_queue_timeout(Duration::from_secs(1), true, |_| {});
+            assert!(Instant::now().duration_since(instant) < Duration::from_millis(100));
+            assert_eq!(recv.drain().collect::<Vec<_>>(), vec![Tester(0)]);
+        }
+
+        #[test]
+        fn capacity_includes_queue() {
+            let (send, mut recv) = bounded::<Tester>(None, Some(2));
+            send.try_send(Tester(0)).unwrap();
+            recv.process_queue_ready(|_| {});
+            send.try_send(Tester(1)).unwrap();
+            assert!(matches!(
+                send.try_send(Tester(2)),
+                Err(TrySendError::Full(Tester(2)))
+            ));
+            assert_eq!(recv.drain().next(), Some(Tester(0)));
+            send.try_send(Tester(2)).unwrap();
+        }
+
+        #[test]
+        fn full_item_merges() {
+            fn merge(this: Tester, that: &mut Tester) -> MergeResult<Tester> {
+                if this == *that {
+                    MergeResult::Success
+                } else {
+                    MergeResult::NotMerged(this)
+                }
+            }
+            let (send, mut recv) = bounded::<Tester>(Some(Box::new(merge)), Some(1));
+            send.try_send(Tester(0)).unwrap();
+            recv.process_queue_ready(|_| {});
+            send.try_send(Tester(0)).unwrap();
+            assert!(send.try_send(Tester(1)).is_err());
+            // merged where it is, so there's nothing left to receive
+            assert!(send.inspect(|_, sent| sent.is_empty()));
+            assert_eq!(recv.drain().collect::<Vec<_>>(), vec![Tester(0)]);
+        }
+
+        #[test]
+        fn full_item_merges_in_place() {
+            fn merge(this: Numbered, that: &mut Numbered) -> MergeResult<Numbered> {
+                if this.0 == that.0 {
+                    MergeResult::Success
+                } else {
+                    MergeResult::NotMerged(this)
+                }
+            }
+            let (send, mut recv) = bounded::<Numbered>(Some(Box::new(merge)), Some(2));
+            send.try_send(Numbered(0)).unwrap();
+            send.try_send(Numbered(1)).unwrap();
+            recv.process_queue_ready(|_| {});
+            send.try_send(Numbered(0)).unwrap();
+            let items: Vec<_> = recv.drain().map(|item| item.0).collect();
+            assert_eq!(items, vec![0, 1]);
+        }
+
+        #[test]
+        fn dropped_receiver_disconnects() {
+            for capacity in [None, Some(1)] {
+                let (send, recv) = bounded::<Tester>(None, capacity);
+                drop(recv);
+                assert!(matches!(
+                    send.try_send(Tester(0)),
+                    Err(TrySendError::Disconnected(Tester(0)))
+                ));
+                assert!(send.send(Tester(0)).is_err());
+            }
+        }
+
+        #[test]
+        fn full_send_blocks_until_space() {
+            let (send, mut recv) = bounded::<Tester>(None, Some(1));
+            send.send(Tester(0)).unwrap();
+            recv.process_queue_ready(|_| {});
+            assert!(matches!(
+                send.send_timeout(Tester(1), Duration::from_millis(1)),
+                Err(SendTimeoutError::Timeout(Tester(1)))
+            ));
+            let sender = std::thread::spawn(move || send.send(Tester(1)));
+            std::thread::sleep(Duration::from_millis(1));
+            assert_eq!(recv.drain().next(), Some(Tester(0)));
+            sender.join().unwrap().unwrap();
+            recv.process_queue_ready(|_| {});
+            assert_eq!(recv.drain().next(), Some(Tester(1)));
+        }
+
+        #[derive(Debug)]
+        struct Numbered(usize);
+
+        impl Prioritised for Numbered {
+            type Priority = ();
+
+            fn priority(&self) -> Self::Priority {}
+        }
+
+        #[test]
+        fn retain_while_receiving() {
+            let (send, mut recv) = channel::<Numbered>(None);
+            // every item numbered below this has been removed, unless it was received before
+            let retained = Arc::new(AtomicUsize::new(0));
+            let done = Arc::new(AtomicBool::new(false));
+            let receiver = std::thread::spawn({
+                let (retained, done) = (retained.clone(), done.clone());
+                move || {
+                    while !done.load(Ordering::SeqCst) {
+                        recv.process_queue_timeout(Duration::from_millis(1), true, |_| {});
+                        for item in recv.drain() {
+                            assert!(item.0 >= retained.load(Ordering::SeqCst), "{:?}", item);
+                        }
+                    }
+                }
+            });
+            for i in 0..10_000 {
+                send.send(Numbered(i)).unwrap();
+                if i % 10 == 9 {
+                    send.retain(|_| false);
+                    retained.store(i + 1, Ordering::SeqCst);
+                }
+            }
+            done.store(true, Ordering::SeqCst);
+            receiver.join().unwrap();
+        }
+
+        #[test]
+        fn inspect_sees_sent_items() {
+            let (send, mut recv) = channel::<Numbered>(None);
+            send.send(Numbered(0)).unwrap();
+            recv.process_queue_ready(|_| {});
+            send.send(Numbered(1)).unwrap();
+            send.send(Numbered(2)).unwrap();
+            let seen = send.inspect(|queue, sent| {
+                (
+                    queue.len(),
+                    sent.iter().map(|item| item.0).collect::<Vec<_>>(),
+                )
+            });
+            assert_eq!(seen, (1, vec![1, 2]));
+            send.send(Numbered(3)).unwrap();
+            recv.process_queue_ready(|_| {});
+            let items: Vec<_> = recv.drain().map(|item| item.0).collect();
+            assert_eq!(items, vec![0, 1, 2, 3]);
+        }
+
         #[test]
         fn bunch_of_items_are_prioritised() {
             let (send, mut recv) = channel::<Tester>(None);
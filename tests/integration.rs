@@ -1,6 +1,12 @@
 use std::{
+    cell::RefCell,
     collections::HashSet,
-    fmt, thread,
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
     time::{Duration, Instant},
 };
 
@@ -104,6 +110,184 @@ fn integration_2_threads_poll_preferred() {
     assert_recv!(helper, "h");
 }
 
+// with a test clock, the recurring jobs are only enqueued once the clock is advanced past when they're due
+#[test]
+fn test_clock_recurrance() {
+    let clock = TestClock::new();
+    let helper = TestHelper::with_clock(1, Duration::from_secs(60), "xy", clock.clone());
+
+    helper.wait_micros(10, 1, 'a');
+    assert_recv!(helper, "a");
+    clock.advance(Duration::from_secs(30));
+    helper.wait_micros(10, 1, 'x'); // resets the interval of x
+    assert_recv!(helper, "x");
+    clock.advance(Duration::from_secs(31));
+    assert_recv!(helper, "y");
+    clock.advance(Duration::from_secs(30));
+    assert_recv!(helper, "x");
+    assert!(helper.recv.try_recv().is_err());
+}
+
+// a cron recurring job is enqueued when the test clock reaches the time of day in the schedule
+#[test]
+fn test_clock_cron() {
+    const NEW_YEAR_2021: Duration = Duration::from_secs(1609459200);
+    let clock = TestClock::at(std::time::UNIX_EPOCH + NEW_YEAR_2021);
+    let (send, recv) = crossbeam_channel::unbounded();
+    let mut runner = JobRunner::builder()
+        .clock(clock.clone())
+        .set_recurring_cron("0 2 * * *", wait_job(&send, &clock, 10, 1, 'c'))
+        .unwrap()
+        .build_manual(1);
+
+    assert_eq!(runner.run_until_idle(), 0);
+    clock.advance(Duration::from_secs(60 * 60));
+    assert_eq!(runner.run_until_idle(), 0);
+    clock.advance(Duration::from_secs(60 * 60));
+    assert_eq!(runner.run_until_idle(), 1);
+    assert_eq!(recv.try_recv(), Ok('c'));
+    clock.advance(Duration::from_secs(24 * 60 * 60 - 1));
+    assert_eq!(runner.run_until_idle(), 0);
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(runner.run_until_idle(), 1);
+    assert_eq!(recv.try_recv(), Ok('c'));
+}
+
+// the recurring job keeps to its schedule from when it last ran before the runner was dropped, rather than starting again from when it is set
+#[test]
+fn recurring_persisted() {
+    let path = std::env::temp_dir().join(format!("gaffer-state-test-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let clock = TestClock::new();
+    let (send, recv) = crossbeam_channel::unbounded();
+    let build = || {
+        let store = Arc::new(FileStateStore::open(&path).unwrap());
+        JobRunner::builder()
+            .clock(clock.clone())
+            .set_recurring_policy(
+                Duration::from_secs(60),
+                RecurringPolicy::default().persist("x", store),
+                wait_job(&send, &clock, 10, 1, 'x'),
+            )
+            .build_manual(1)
+    };
+
+    let mut runner = build();
+    clock.advance(Duration::from_secs(61));
+    assert_eq!(runner.run_until_idle(), 1);
+    clock.advance(Duration::from_secs(30));
+    runner.send(wait_job(&send, &clock, 10, 1, 'a')).unwrap();
+    drop(runner);
+
+    let mut runner = build();
+    clock.advance(Duration::from_secs(29));
+    assert_eq!(runner.run_until_idle(), 0);
+    // a minute since x last ran, rather than since it was set again
+    clock.advance(Duration::from_secs(2));
+    assert_eq!(runner.run_until_idle(), 1);
+    assert_eq!(recv.try_iter().collect::<String>(), "xx");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn send_after() {
+    let clock = TestClock::new();
+    let helper = TestHelper::new_runner(JobRunner::builder().clock(clock.clone()).build(1));
+
+    helper
+        .runner
+        .send_after(Duration::from_secs(60), helper.job(10, 1, 'b'))
+        .unwrap();
+    helper
+        .runner
+        .send_at(
+            clock.now() + Duration::from_secs(30),
+            helper.job(10, 1, 'a'),
+        )
+        .unwrap();
+    helper.wait_micros(10, 1, 'x');
+    assert_recv!(helper, "x");
+    clock.advance(Duration::from_secs(30));
+    assert_recv!(helper, "a");
+    clock.advance(Duration::from_secs(30));
+    assert_recv!(helper, "b");
+    assert!(helper.recv.try_recv().is_err());
+}
+
+// b's deadline passes whilst a is running, so it expires rather than being executed
+#[test]
+fn deadline_expired() {
+    let clock = TestClock::new();
+    let (expired_send, expired_recv) = crossbeam_channel::unbounded();
+    let helper = TestHelper::new_runner(
+        JobRunner::builder()
+            .clock(clock.clone())
+            .on_expired(move |job: WaitJob| expired_send.send(job.key).unwrap())
+            .build(1),
+    );
+
+    helper.wait_micros(20_000, 1, 'a');
+    helper
+        .runner
+        .send(WaitJob {
+            deadline: Some(clock.now() + Duration::from_secs(30)),
+            ..helper.job(10, 1, 'b')
+        })
+        .unwrap();
+    helper.wait_micros(10, 1, 'c');
+    clock.advance(Duration::from_secs(60));
+    assert_recv!(helper, "ac");
+    assert_eq!(expired_recv.recv_timeout(TIMEOUT), Ok('b'));
+    assert_eq!(helper.runner.metrics().expired, 1);
+}
+
+// a panic in the on_expired callback should not stop the runner
+#[test]
+fn on_expired_panic() {
+    let clock = TestClock::new();
+    let helper = TestHelper::new_runner(
+        JobRunner::builder()
+            .clock(clock.clone())
+            .on_expired(|_| panic!("on_expired panicked"))
+            .build(1),
+    );
+
+    helper.wait_micros(20_000, 1, 'a');
+    helper
+        .runner
+        .send(WaitJob {
+            deadline: Some(clock.now()),
+            ..helper.job(10, 1, 'b')
+        })
+        .unwrap();
+    clock.advance(Duration::from_secs(1));
+    assert_recv!(helper, "a");
+    helper.wait_micros(10, 1, 'c');
+    assert_recv!(helper, "c");
+    assert_eq!(helper.runner.metrics().expired, 1);
+}
+
+// delayed jobs which aren't due yet aren't executed whilst draining, they're returned
+#[test]
+fn send_after_shutdown() {
+    let helper = TestHelper::new_runner(JobRunner::builder().build(1));
+
+    helper
+        .runner
+        .send_after(Duration::from_secs(60), helper.job(10, 1, 'a'))
+        .unwrap();
+    helper.wait_micros(10, 1, 'b');
+    assert_recv!(helper, "b");
+    let remaining = helper
+        .runner
+        .clone()
+        .shutdown(ShutdownMode::Drain, Some(TIMEOUT));
+    assert_eq!(
+        remaining.into_iter().map(|job| job.key).collect::<String>(),
+        "a"
+    );
+}
+
 // a panicking job should not kill the thread
 #[test]
 fn panic_in_job() {
@@ -135,57 +319,869 @@ fn panic_in_job() {
     assert!(recv.recv_timeout(Duration::from_millis(500)).is_ok());
 }
 
+// the panic is reported and the same worker carries on, the panicked job's exclusion is released
+#[test]
+fn on_panic() {
+    let (send, recv) = crossbeam_channel::unbounded();
+    let (panic_send, panic_recv) = crossbeam_channel::unbounded();
+
+    #[derive(Debug)]
+    struct PanicJob(char, Sender<thread::ThreadId>);
+    impl Job for PanicJob {
+        type Priority = ();
+
+        fn priority(&self) -> Self::Priority {}
+
+        type Exclusion = ExclusionOption<u8>;
+
+        fn exclusion(&self) -> Self::Exclusion {
+            ExclusionOption::Some(1)
+        }
+
+        fn execute(self) {
+            if self.0 == 'p' {
+                panic!("job {} panicked", self.0);
+            }
+            self.1.send(thread::current().id()).unwrap();
+        }
+    }
+    let runner = JobRunner::builder()
+        .on_panic(move |panic| panic_send.send(panic).unwrap())
+        .build(1);
+    runner.send(PanicJob('a', send.clone())).unwrap();
+    runner.send(PanicJob('p', send.clone())).unwrap();
+    runner.send(PanicJob('b', send)).unwrap();
+    let before = recv.recv_timeout(TIMEOUT).unwrap();
+    let after = recv.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(before, after);
+    let panic = panic_recv.recv_timeout(TIMEOUT).unwrap();
+    assert!(panic.job.starts_with("PanicJob('p'"), "{:?}", panic);
+    assert_eq!(panic.worker_index, 0);
+    assert_eq!(panic.message(), Some("job p panicked"));
+}
+
+// a job which panics is consumed, so its dead letter can't be requeued
+#[test]
+fn dead_letter_panicked() {
+    let dead_letters = InMemoryDeadLetters::new(10);
+    let helper = TestHelper::new_runner(
+        JobRunner::builder()
+            .dead_letters(dead_letters.clone())
+            .build(1),
+    );
+    // the job panics when it can't send
+    let (send, recv) = crossbeam_channel::unbounded();
+    drop(recv);
+    helper
+        .runner
+        .send(WaitJob {
+            send,
+            ..helper.job(10, 1, 'p')
+        })
+        .unwrap();
+    helper.wait_micros(10, 1, 'a');
+    assert_recv!(helper, "a");
+    let letters = dead_letters.letters();
+    assert_eq!(letters.len(), 1);
+    assert!(letters[0].job.is_none());
+    assert_eq!(letters[0].attempts, 1);
+    assert!(matches!(
+        letters[0].reason,
+        DeadLetterReason::Panicked(Some(_))
+    ));
+    assert_eq!(helper.runner.requeue_dead_letters(|_| true), 0);
+    assert_eq!(dead_letters.len(), 1);
+}
+
+#[test]
+fn shutdown_drain() {
+    let helper = TestHelper::new_runner(JobRunner::builder().build(1));
+
+    helper.wait_micros(1000, 1, 'a');
+    helper.wait_micros(10, 1, 'b');
+    helper.wait_micros(10, 1, 'c');
+    let remaining = helper.runner.clone().shutdown(ShutdownMode::Drain, None);
+    assert!(remaining.is_empty());
+    assert_recv!(helper, "abc");
+    assert!(helper.runner.send(helper.job(10, 1, 'd')).is_err());
+}
+
+// the supervisor shouldn't wait for new jobs once there is nothing left to drain
+#[test]
+fn shutdown_drain_idle() {
+    let helper = TestHelper::new_runner(JobRunner::builder().build(2));
+
+    helper.wait_micros(10, 1, 'a');
+    assert_recv!(helper, "a");
+    let before = Instant::now();
+    let remaining = helper.runner.clone().shutdown(ShutdownMode::Drain, None);
+    // otherwise the supervisor waits 5s for new jobs
+    assert!(
+        before.elapsed() < Duration::from_secs(1),
+        "{:?}",
+        before.elapsed()
+    );
+    assert!(remaining.is_empty());
+    assert_eq!(helper.runner.snapshot().workers, [WorkerStatus::Stopped; 2]);
+}
+
+#[test]
+fn shutdown_finish_running() {
+    let helper = TestHelper::new_runner(JobRunner::builder().build(1));
+
+    helper.wait_micros(20_000, 1, 'a');
+    helper.wait_for_working(1); // a gets picked up alone
+    helper.wait_micros(10, 1, 'b');
+    helper.wait_micros(10, 2, 'c');
+    let remaining = helper
+        .runner
+        .clone()
+        .shutdown(ShutdownMode::FinishRunning, None);
+    assert_eq!(
+        remaining.into_iter().map(|job| job.key).collect::<String>(),
+        "cb"
+    );
+    assert_recv!(helper, "a");
+}
+
+// a job can't wait for its own worker to exit, so it panics rather than deadlocking
+#[test]
+fn shutdown_from_job() {
+    let (send, recv) = crossbeam_channel::unbounded();
+
+    struct ShutdownJob(Arc<Mutex<Option<JobRunner<ShutdownJob>>>>, Sender<()>);
+    impl Job for ShutdownJob {
+        type Priority = ();
+
+        fn priority(&self) -> Self::Priority {}
+
+        type Exclusion = NoExclusion;
+
+        fn exclusion(&self) -> Self::Exclusion {
+            NoExclusion
+        }
+
+        fn execute(self) {
+            let runner = self.0.lock().unwrap().take();
+            if let Some(runner) = runner {
+                runner.shutdown(ShutdownMode::Drain, None);
+            }
+            self.1.send(()).unwrap();
+        }
+    }
+    let runner = JobRunner::builder().build(1);
+    let shared = Arc::new(Mutex::new(Some(runner.clone())));
+    runner
+        .send(ShutdownJob(shared.clone(), send.clone()))
+        .unwrap();
+    // the first job panics, leaving the worker free for the second
+    runner.send(ShutdownJob(shared, send)).unwrap();
+    assert!(recv.recv_timeout(TIMEOUT).is_ok());
+}
+
+#[test]
+fn shutdown_immediate() {
+    let helper = TestHelper::new_runner(JobRunner::builder().build(1));
+
+    helper.send_blocked(1, 'a');
+    helper.wait_for_working(1); // a gets picked up alone
+    helper.wait_micros(10, 1, 'b');
+    let remaining = helper
+        .runner
+        .clone()
+        .shutdown(ShutdownMode::Immediate, None);
+    assert_eq!(
+        remaining.into_iter().map(|job| job.key).collect::<String>(),
+        "b"
+    );
+    // it didn't wait for a, which finishes on its own
+    assert!(helper.recv.try_recv().is_err());
+    helper.release();
+    assert_recv!(helper, "a");
+}
+
+// once the runner is dropped, the queue is drained and then the recurring jobs and workers are dropped
+#[test]
+fn drop_runner() {
+    let helper = TestHelper::new(1, Duration::from_millis(1), "x");
+
+    helper.wait_micros(1000, 1, 'a');
+    helper.wait_micros(10, 1, 'b');
+    let TestHelper {
+        runner, send, recv, ..
+    } = helper;
+    drop(runner);
+    drop(send);
+    let received: String = recv.iter().collect();
+    assert!(received.ends_with("ab"), "{:?}", received);
+}
+
+#[test]
+fn drop_runner_abandon_queue() {
+    let helper = TestHelper::new_runner(
+        JobRunner::builder()
+            .drop_mode(ShutdownMode::FinishRunning)
+            .build(1),
+    );
+
+    helper.send_blocked(1, 'a');
+    helper.wait_for_working(1); // a gets picked up alone
+    helper.wait_micros(10, 1, 'b');
+    let TestHelper {
+        runner,
+        send,
+        recv,
+        release,
+        ..
+    } = helper;
+    drop(runner);
+    drop(send);
+    release.send(()).unwrap();
+    assert_eq!(recv.iter().collect::<String>(), "a");
+}
+
+#[test]
+fn queue_capacity() {
+    let helper = TestHelper::new_runner(JobRunner::builder().queue_capacity(1).build(1));
+
+    helper.send_blocked(1, 'a');
+    helper.wait_for_working(1); // a gets picked up alone
+    helper.wait_micros(10, 1, 'b');
+    assert!(matches!(
+        helper.runner.try_send(helper.job(10, 1, 'c')),
+        Err(crossbeam_channel::TrySendError::Full(_))
+    ));
+    helper.release();
+    helper
+        .runner
+        .send_timeout(helper.job(10, 1, 'c'), TIMEOUT)
+        .unwrap();
+    assert_recv!(helper, "abc");
+}
+
+#[test]
+fn tracked_status() {
+    let helper = TestHelper::new_runner(JobRunner::builder().build(1));
+
+    let a = helper
+        .runner
+        .send_tracked(helper.blocked_job(1, 'a'))
+        .unwrap();
+    wait_for_status(&a, JobStatus::Running(0)); // a gets picked up alone
+    let b = helper.runner.send_tracked(helper.job(10, 1, 'b')).unwrap();
+    assert_ne!(a.id(), b.id());
+    assert_eq!(b.status(), JobStatus::Queued);
+    helper.release();
+    assert_recv!(helper, "ab");
+    wait_for_status(&a, JobStatus::Completed);
+    wait_for_status(&b, JobStatus::Completed);
+}
+
+#[test]
+fn tracked_cancel() {
+    let helper = TestHelper::new_runner(JobRunner::builder().build(1));
+
+    let a = helper
+        .runner
+        .send_tracked(helper.blocked_job(1, 'a'))
+        .unwrap();
+    wait_for_status(&a, JobStatus::Running(0)); // a gets picked up alone
+    let b = helper.runner.send_tracked(helper.job(10, 2, 'b')).unwrap();
+    helper.wait_micros(10, 1, 'c');
+    assert!(!a.cancel());
+    assert!(b.cancel());
+    assert_eq!(b.status(), JobStatus::Cancelled);
+    helper.release();
+    assert_recv!(helper, "ac");
+}
+
+#[test]
+fn tracked_merge() {
+    let helper = TestHelper::new_runner(
+        JobRunner::builder()
+            .enable_merge(|this: WaitJob, that: &mut WaitJob| {
+                if this.key == that.key {
+                    MergeResult::Success
+                } else {
+                    MergeResult::NotMerged(this)
+                }
+            })
+            .build(1),
+    );
+
+    helper.send_blocked(1, 'a');
+    helper.wait_for_working(1); // a gets picked up alone
+    let b1 = helper.runner.send_tracked(helper.job(10, 1, 'b')).unwrap();
+    let b2 = helper.runner.send_tracked(helper.job(10, 1, 'b')).unwrap();
+    // they are merged once the supervisor receives them, after a has finished
+    helper.release();
+    wait_for_status(&b2, JobStatus::Merged(b1.id()));
+    assert_recv!(helper, "ab");
+    wait_for_status(&b1, JobStatus::Completed);
+    assert_eq!(b2.resolved_status(), JobStatus::Completed);
+    assert!(helper.recv.recv_timeout(Duration::from_millis(1)).is_err());
+}
+
+#[test]
+fn retain_queued() {
+    let helper = TestHelper::new_runner(JobRunner::builder().build(1));
+
+    helper.send_blocked(1, 'a');
+    helper.wait_for_working(1); // a gets picked up alone
+    helper.wait_micros(10, 1, 'b');
+    let c = helper.runner.send_tracked(helper.job(10, 1, 'c')).unwrap();
+    helper.wait_micros(10, 1, 'd');
+    helper.runner.retain(|job| job.key != 'c');
+    assert_eq!(c.status(), JobStatus::Cancelled);
+    helper.release();
+    assert_recv!(helper, "abd");
+}
+
+#[test]
+fn modify_queued() {
+    let helper = TestHelper::new_runner(JobRunner::builder().build(1));
+
+    helper.send_blocked(1, 'a');
+    helper.wait_for_working(1); // a gets picked up alone
+    helper.wait_micros(10, 2, 'b');
+    helper.wait_micros(10, 1, 'c');
+    helper.wait_micros(10, 1, 'd');
+    helper.runner.modify_queued(|job| {
+        if job.key == 'd' {
+            job.priority = 3;
+        }
+    });
+    helper.release();
+    assert_recv!(helper, "adbc");
+}
+
+#[test]
+fn snapshot() {
+    let helper = TestHelper::new_runner(JobRunner::builder().build(1));
+
+    let before = Instant::now();
+    helper
+        .runner
+        .send(WaitJob {
+            exclusion: Some('a'),
+            ..helper.blocked_job(1, 'a')
+        })
+        .unwrap();
+    helper.wait_for_working(1); // a gets picked up alone
+    helper.wait_micros(10, 1, 'b');
+    helper.wait_micros(10, 2, 'c');
+    helper.wait_micros(10, 1, 'd');
+    let snapshot = helper.runner.snapshot();
+    let queue: Vec<_> = snapshot
+        .queue
+        .iter()
+        .map(|(priority, jobs)| {
+            (
+                *priority,
+                jobs.iter().map(|job| job.key).collect::<String>(),
+            )
+        })
+        .collect();
+    assert_eq!(queue, vec![(2, "c".to_owned()), (1, "bd".to_owned())]);
+    assert!(matches!(
+        snapshot.workers[..],
+        [WorkerStatus::Working { exclusion: ExclusionOption::Some('a'), started }] if started > before
+    ));
+    helper.release();
+    assert_recv!(helper, "acbd");
+}
+
+#[test]
+fn set_threads() {
+    let helper = TestHelper::new_runner(JobRunner::builder().build(1));
+
+    helper.send_blocked(1, 'a');
+    helper.wait_for_working(1); // a gets picked up alone
+    helper.send_blocked(1, 'b');
+    helper.runner.set_threads(2);
+    helper.wait_for_working(2); // b gets picked up by the new thread
+    helper.runner.set_threads(1);
+    helper.release();
+    helper.release();
+    assert_recv_unordered!(helper, "ab");
+    helper.wait_for_running(1);
+    helper.wait_micros(10, 1, 'c');
+    assert_recv!(helper, "c");
+}
+
+#[test]
+fn autoscale() {
+    let helper = TestHelper::new_runner(
+        JobRunner::builder()
+            .autoscale(1, 3, Duration::from_millis(5))
+            .build(1),
+    );
+
+    for key in ['a', 'b', 'c'] {
+        helper.send_blocked(1, key);
+    }
+    helper.wait_micros(10, 1, 'd');
+    helper.wait_for_working(3); // a, b & c get picked up by the supervisor and 2 new workers
+    assert_eq!(helper.runner.snapshot().workers.len(), 3);
+    for _ in 0..3 {
+        helper.release();
+    }
+    // d is picked up by whichever worker is freed first, so it can finish before the others
+    assert_recv_unordered!(helper, "abcd");
+    helper.wait_for_running(1); // the idle workers retire
+}
+
+// the workers started by autoscaling are joined on shutdown, along with the initial ones
+#[test]
+fn autoscale_shutdown_joins() {
+    thread_local! {
+        // dropped once the thread has exited
+        static ALIVE: RefCell<Option<Sender<thread::ThreadId>>> = const { RefCell::new(None) };
+    }
+
+    struct ThreadJob(Sender<thread::ThreadId>);
+    impl Job for ThreadJob {
+        type Priority = ();
+
+        fn priority(&self) -> Self::Priority {}
+
+        type Exclusion = NoExclusion;
+
+        fn exclusion(&self) -> Self::Exclusion {
+            NoExclusion
+        }
+
+        fn execute(self) {
+            thread::sleep(Duration::from_millis(5));
+            self.0.send(thread::current().id()).unwrap();
+            ALIVE.with(|alive| *alive.borrow_mut() = Some(self.0));
+        }
+    }
+    let (send, recv) = crossbeam_channel::unbounded();
+    let runner = JobRunner::builder()
+        .autoscale(1, 3, Duration::from_secs(60))
+        .build(1);
+    for _ in 0..3 {
+        runner.send(ThreadJob(send.clone())).unwrap();
+    }
+    drop(send);
+    let threads: HashSet<_> = (0..3)
+        .map(|_| recv.recv_timeout(TIMEOUT).unwrap())
+        .collect();
+    assert_eq!(threads.len(), 3);
+    runner.shutdown(ShutdownMode::FinishRunning, Some(TIMEOUT));
+    assert_eq!(
+        recv.try_recv(),
+        Err(crossbeam_channel::TryRecvError::Disconnected)
+    );
+}
+
+#[test]
+fn recurring_at_runtime() {
+    let helper = TestHelper::new_runner(JobRunner::builder().build(1));
+
+    let handle = helper.runner.add_recurring(
+        Duration::from_millis(1),
+        Instant::now(),
+        helper.job(40, 2, 'x'),
+    );
+    assert_recv!(helper, "x");
+    assert_eq!(helper.runner.recurring_handles().len(), 1);
+    handle.pause();
+    helper.pause(5000); // any recurrance which was already ready is done
+    while helper.recv.try_recv().is_ok() {}
+    assert!(helper.recv.recv_timeout(Duration::from_millis(10)).is_err());
+    handle.set_interval(Duration::from_secs(60));
+    handle.resume();
+    handle.trigger_now();
+    assert_recv!(helper, "x");
+    assert!(helper.recv.recv_timeout(Duration::from_millis(10)).is_err());
+    handle.cancel();
+    assert!(helper.runner.recurring_handles().is_empty());
+}
+
+#[test]
+fn recurring_handles_include_builder() {
+    let helper = TestHelper::with_builder(1, |builder, job| {
+        builder.set_recurring(Duration::from_millis(1), Instant::now(), job(40, 2, 'x'))
+    });
+
+    assert_recv!(helper, "x");
+    let handles = helper.runner.recurring_handles();
+    assert_eq!(handles.len(), 1);
+    handles[0].pause();
+    helper.pause(5000); // any recurrance which was already ready is done
+    while helper.recv.try_recv().is_ok() {}
+    assert!(helper.recv.recv_timeout(Duration::from_millis(10)).is_err());
+
+    let added = helper.runner.add_recurring_job(Limited {
+        job: helper.job(40, 2, 'y'),
+        remaining: 2,
+    });
+    assert_recv!(helper, "yy");
+    assert_eq!(helper.runner.recurring_handles().len(), 2);
+    added.cancel();
+    handles[0].cancel();
+    assert!(helper.runner.recurring_handles().is_empty());
+}
+
+#[test]
+fn recurring_with_factory() {
+    let keys = Mutex::new("abc".chars());
+    let helper = TestHelper::with_builder(1, |builder, job| {
+        let job = job(40, 2, 'z');
+        builder.set_recurring_with(
+            Duration::from_millis(1),
+            |job: &WaitJob| job.key.is_ascii_lowercase(),
+            move || WaitJob {
+                created: Instant::now(),
+                key: keys.lock().unwrap().next().unwrap_or('z'),
+                ..job.clone()
+            },
+        )
+    });
+
+    assert_recv!(helper, "abc");
+}
+
+#[test]
+fn recurring_since_completed() {
+    let helper = TestHelper::with_builder(3, |builder, job| {
+        builder.set_recurring_anchored(
+            Duration::from_micros(100),
+            Instant::now(),
+            RecurrenceAnchor::SinceCompleted,
+            job(2000, 2, 'x'),
+        )
+    });
+
+    assert_recv!(helper, "xx");
+    for _ in 0..10 {
+        // the job takes longer than the interval, but a new one isn't enqueued until it has completed
+        let snapshot = helper.runner.snapshot();
+        assert!(snapshot.queue.is_empty(), "{:?}", snapshot);
+        assert!(
+            snapshot
+                .workers
+                .iter()
+                .filter(|worker| matches!(worker, WorkerStatus::Working { .. }))
+                .count()
+                <= 1,
+            "{:?}",
+            snapshot
+        );
+        helper.pause(500);
+    }
+}
+
+#[test]
+fn recurring_singleton() {
+    let helper = TestHelper::with_builder(3, |builder, job| {
+        builder.set_recurring_policy(
+            Duration::from_millis(1),
+            RecurringPolicy::default().singleton(true),
+            job(40, 2, 'x'),
+        )
+    });
+
+    helper.send_blocked(1, 'x');
+    helper.wait_for_working(1);
+    for _ in 0..5 {
+        // the sent job is still running, so the recurring job isn't enqueued
+        let snapshot = helper.runner.snapshot();
+        assert!(snapshot.queue.is_empty(), "{:?}", snapshot);
+        assert_eq!(
+            snapshot
+                .workers
+                .iter()
+                .filter(|worker| matches!(worker, WorkerStatus::Working { .. }))
+                .count(),
+            1,
+            "{:?}",
+            snapshot
+        );
+        helper.pause(400);
+    }
+    helper.release();
+    assert_recv!(helper, "xx");
+}
+
+#[test]
+fn recurring_run_immediately() {
+    let helper = TestHelper::with_builder(1, |builder, job| {
+        builder.set_recurring_policy(
+            Duration::from_secs(60),
+            RecurringPolicy::default()
+                .run_immediately()
+                .jitter(Jitter::Percent(10)),
+            job(40, 2, 'x'),
+        )
+    });
+
+    assert_recv!(helper, "x");
+    assert!(helper.recv.recv_timeout(Duration::from_millis(10)).is_err());
+}
+
+/// recurs straight away until it has been enqueued `remaining` times
+struct Limited {
+    job: WaitJob,
+    remaining: usize,
+}
+
+impl RecurringJob<WaitJob> for Limited {
+    fn get(&self) -> Option<WaitJob> {
+        (self.remaining > 0).then(|| self.job.clone())
+    }
+
+    fn job_enqueued(&mut self, job: &WaitJob) {
+        if job.key == self.job.key {
+            self.remaining = self.remaining.saturating_sub(1);
+        }
+    }
+
+    fn max_sleep(&self) -> Instant {
+        if self.remaining > 0 {
+            Instant::now()
+        } else {
+            Instant::now() + Duration::from_secs(60)
+        }
+    }
+
+    fn trigger(&self) -> WaitJob {
+        self.job.clone()
+    }
+}
+
+#[test]
+fn custom_recurring() {
+    let helper = TestHelper::with_builder(1, |builder, job| {
+        builder.add_recurring(Limited {
+            job: job(40, 2, 'x'),
+            remaining: 3,
+        })
+    });
+
+    assert_recv!(helper, "xxx");
+    assert!(helper.recv.recv_timeout(Duration::from_millis(10)).is_err());
+}
+
+/// panics the first time it is polled, then never recurs
+#[derive(Default)]
+struct PanicOnce(AtomicBool);
+
+impl RecurringJob<WaitJob> for PanicOnce {
+    fn get(&self) -> Option<WaitJob> {
+        if !self.0.swap(true, Ordering::SeqCst) {
+            panic!("recurring job panicked");
+        }
+        None
+    }
+
+    fn job_enqueued(&mut self, _job: &WaitJob) {}
+
+    fn max_sleep(&self) -> Instant {
+        if self.0.load(Ordering::SeqCst) {
+            Instant::now() + Duration::from_secs(60)
+        } else {
+            Instant::now()
+        }
+    }
+
+    fn trigger(&self) -> WaitJob {
+        unreachable!()
+    }
+}
+
+// a panic on the supervisor outside of a job should start a replacement, which carries on supervising
+#[test]
+fn supervisor_panic_recovered() {
+    let helper = TestHelper::new_runner(
+        JobRunner::builder()
+            .add_recurring(PanicOnce::default())
+            .build(2),
+    );
+
+    thread::sleep(Duration::from_millis(10));
+    helper.wait_micros(10, 1, 'a');
+    helper.wait_micros(10, 1, 'b');
+    assert_recv_unordered!(helper, "ab");
+    helper.wait_for_workers(|workers| {
+        matches!(
+            workers,
+            [WorkerStatus::Supervisor, WorkerStatus::Available]
+                | [WorkerStatus::Available, WorkerStatus::Supervisor]
+        )
+    });
+}
+
+/// the status is updated after the job has sent it's key, so it might take a moment to change
+fn wait_for_status(handle: &JobHandle<WaitJob>, status: JobStatus) {
+    let deadline = Instant::now() + TIMEOUT;
+    while handle.status() != status {
+        assert!(
+            Instant::now() < deadline,
+            "{:?} didn't become {:?}",
+            handle,
+            status
+        );
+        thread::sleep(Duration::from_micros(100));
+    }
+}
+
 struct TestHelper {
     runner: JobRunner<WaitJob>,
     send: crossbeam_channel::Sender<char>,
     recv: crossbeam_channel::Receiver<char>,
+    clock: Arc<dyn Clock>,
+    /// each message releases one of the blocked jobs
+    release: crossbeam_channel::Sender<()>,
+    gate: crossbeam_channel::Receiver<()>,
 }
 
 impl TestHelper {
     fn new(thread_num: usize, interval: Duration, recurring: &str) -> Self {
-        let (send, recv) = crossbeam_channel::unbounded();
+        Self::with_clock(thread_num, interval, recurring, SystemClock)
+    }
 
-        let mut builder = gaffer::JobRunner::builder();
-        for key in recurring.chars() {
-            builder = builder.set_recurring(
-                interval,
-                Instant::now(),
-                WaitJob {
-                    created: Instant::now(),
-                    duration: Duration::from_micros(40),
-                    priority: 2,
-                    exclusion: None,
-                    key,
-                    send: send.clone(),
-                },
-            )
+    /// recurring jobs are scheduled using `clock`
+    fn with_clock(
+        thread_num: usize,
+        interval: Duration,
+        recurring: &str,
+        clock: impl Clock + Clone + 'static,
+    ) -> Self {
+        let now = clock.now();
+        Self::with_clock_builder(thread_num, clock, |mut builder, job| {
+            for key in recurring.chars() {
+                builder = builder.set_recurring(interval, now, job(40, 2, key));
+            }
+            builder
+        })
+    }
+
+    /// the runner is built from the builder returned by `f`, which is passed a function creating jobs like [`TestHelper::job`]
+    fn with_builder(
+        thread_num: usize,
+        f: impl FnOnce(Builder<WaitJob>, &dyn Fn(u64, u8, char) -> WaitJob) -> Builder<WaitJob>,
+    ) -> Self {
+        Self::with_clock_builder(thread_num, SystemClock, f)
+    }
+
+    /// like [`TestHelper::with_builder`], with jobs scheduled using `clock`
+    fn with_clock_builder(
+        thread_num: usize,
+        clock: impl Clock + Clone + 'static,
+        f: impl FnOnce(Builder<WaitJob>, &dyn Fn(u64, u8, char) -> WaitJob) -> Builder<WaitJob>,
+    ) -> Self {
+        let (send, recv) = crossbeam_channel::unbounded();
+        let job = |micros, priority, key| wait_job(&send, &clock, micros, priority, key);
+        let runner = f(JobRunner::builder().clock(clock.clone()), &job).build(thread_num);
+        let (release, gate) = crossbeam_channel::unbounded();
+        Self {
+            runner,
+            send,
+            recv,
+            clock: Arc::new(clock),
+            release,
+            gate,
         }
-        let runner = builder.build(thread_num);
-        Self { runner, send, recv }
     }
 
     fn new_runner(runner: JobRunner<WaitJob>) -> Self {
         let (send, recv) = crossbeam_channel::unbounded();
-        Self { runner, send, recv }
+        let (release, gate) = crossbeam_channel::unbounded();
+        Self {
+            runner,
+            send,
+            recv,
+            clock: Arc::new(SystemClock),
+            release,
+            gate,
+        }
     }
 
     fn wait_micros(&self, micros: u64, priority: u8, key: char) {
-        let send = self.send.clone();
-        self.runner
-            .send(WaitJob {
-                created: Instant::now(),
-                duration: Duration::from_micros(micros),
-                priority,
-                exclusion: None,
-                key,
-                send,
-            })
-            .unwrap()
+        self.runner.send(self.job(micros, priority, key)).unwrap()
+    }
+
+    fn job(&self, micros: u64, priority: u8, key: char) -> WaitJob {
+        wait_job(&self.send, &*self.clock, micros, priority, key)
+    }
+
+    /// a job which keeps running until it is released by [`TestHelper::release`], or the timeout passes
+    fn blocked_job(&self, priority: u8, key: char) -> WaitJob {
+        WaitJob {
+            gate: Some(self.gate.clone()),
+            ..self.job(10, priority, key)
+        }
+    }
+
+    fn send_blocked(&self, priority: u8, key: char) {
+        self.runner.send(self.blocked_job(priority, key)).unwrap()
+    }
+
+    /// let one of the blocked jobs finish
+    fn release(&self) {
+        self.release.send(()).unwrap();
     }
 
     fn pause(&self, micros: u64) {
         thread::sleep(Duration::from_micros(micros));
     }
+
+    /// wait for the workers to get into a state matching `f`, they are updated as the runner picks jobs up, so it might take a moment
+    fn wait_for_workers(&self, f: impl Fn(&[WorkerStatus<ExclusionOption<char>>]) -> bool) {
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            let workers = self.runner.snapshot().workers;
+            if f(&workers) {
+                return;
+            }
+            assert!(Instant::now() < deadline, "{:?}", workers);
+            thread::sleep(Duration::from_micros(100));
+        }
+    }
+
+    /// wait for `count` of the workers to be executing jobs
+    fn wait_for_working(&self, count: usize) {
+        self.wait_for_workers(|workers| {
+            workers
+                .iter()
+                .filter(|worker| matches!(worker, WorkerStatus::Working { .. }))
+                .count()
+                == count
+        });
+    }
+
+    /// wait for the workers to stop until there are only `count` left
+    fn wait_for_running(&self, count: usize) {
+        self.wait_for_workers(|workers| {
+            workers
+                .iter()
+                .filter(|worker| **worker != WorkerStatus::Stopped)
+                .count()
+                == count
+        });
+    }
+}
+
+fn wait_job(
+    send: &crossbeam_channel::Sender<char>,
+    clock: &dyn Clock,
+    micros: u64,
+    priority: u8,
+    key: char,
+) -> WaitJob {
+    WaitJob {
+        created: clock.now(),
+        duration: Duration::from_micros(micros),
+        priority,
+        exclusion: None,
+        deadline: None,
+        key,
+        send: send.clone(),
+        gate: None,
+    }
 }
 
 #[derive(Clone)]
@@ -194,8 +1190,11 @@ struct WaitJob {
     duration: Duration,
     priority: u8,
     exclusion: Option<char>,
+    deadline: Option<Instant>,
     key: char,
     send: crossbeam_channel::Sender<char>,
+    /// the job waits for a message before finishing, see [`TestHelper::blocked_job`]
+    gate: Option<crossbeam_channel::Receiver<()>>,
 }
 
 impl RecurrableJob for WaitJob {
@@ -214,10 +1213,17 @@ impl Job for WaitJob {
     type Priority = u8;
 
     fn priority(&self) -> Self::Priority {
-        self.priority.into()
+        self.priority
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.deadline
     }
 
     fn execute(self) {
+        if let Some(gate) = &self.gate {
+            let _ = gate.recv_timeout(TIMEOUT);
+        }
         thread::sleep(self.duration);
         println!("Completed job {:?}", self);
         self.send.send(self.key).unwrap();
@@ -237,3 +1243,122 @@ impl fmt::Debug for WaitJob {
         )
     }
 }
+
+#[cfg(feature = "journal")]
+mod journal {
+    use serde::{Deserialize, Serialize};
+    use std::{fs, process, sync::Mutex};
+
+    use super::*;
+
+    /// the keys of the jobs which have been executed, by all of the tests
+    static EXECUTED: Mutex<Vec<char>> = Mutex::new(Vec::new());
+
+    /// the keys which have been executed out of `keys`, as the tests run in parallel
+    fn executed(keys: &str) -> String {
+        EXECUTED
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|key| keys.contains(**key))
+            .collect()
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Journaled {
+        key: char,
+        priority: u8,
+        millis: u64,
+    }
+
+    impl Job for Journaled {
+        type Exclusion = NoExclusion;
+
+        fn exclusion(&self) -> Self::Exclusion {
+            NoExclusion
+        }
+
+        type Priority = u8;
+
+        fn priority(&self) -> Self::Priority {
+            self.priority
+        }
+
+        fn execute(self) {
+            thread::sleep(Duration::from_millis(self.millis));
+            EXECUTED.lock().unwrap().push(self.key);
+        }
+    }
+
+    fn job(key: char, priority: u8, millis: u64) -> Journaled {
+        Journaled {
+            key,
+            priority,
+            millis,
+        }
+    }
+
+    #[test]
+    fn restored_after_shutdown() {
+        let path = std::env::temp_dir().join(format!("gaffer-journal-test-{}", process::id()));
+        let _ = fs::remove_file(&path);
+
+        let runner = JobRunner::<Journaled>::builder()
+            .journal(&path)
+            .unwrap()
+            .build(1);
+        let a = runner.send_tracked(job('a', 1, 20)).unwrap();
+        while a.status() != JobStatus::Running(0) {
+            thread::sleep(Duration::from_micros(100)); // a starts
+        }
+        runner.send(job('b', 1, 0)).unwrap();
+        runner.send(job('c', 2, 0)).unwrap();
+        let remaining = runner.shutdown(ShutdownMode::FinishRunning, Some(TIMEOUT));
+        assert_eq!(remaining.len(), 2);
+        assert_eq!(executed("abc"), "a");
+
+        let runner = JobRunner::<Journaled>::builder()
+            .journal(&path)
+            .unwrap()
+            .build(1);
+        runner.shutdown(ShutdownMode::Drain, Some(TIMEOUT));
+        assert_eq!(executed("abc"), "acb");
+
+        // everything was completed, so nothing is restored
+        let runner = JobRunner::<Journaled>::builder()
+            .journal(&path)
+            .unwrap()
+            .build(1);
+        runner.shutdown(ShutdownMode::Drain, Some(TIMEOUT));
+        assert_eq!(executed("abc"), "acb");
+        fs::remove_file(&path).unwrap();
+    }
+
+    // the jobs which were still queued when the runner was dropped are executed by the next runner with the journal
+    #[test]
+    fn restored_after_drop() {
+        let path = std::env::temp_dir().join(format!("gaffer-journal-drop-test-{}", process::id()));
+        let _ = fs::remove_file(&path);
+
+        // none of the jobs can start
+        let runner = JobRunner::<Journaled>::builder()
+            .journal(&path)
+            .unwrap()
+            .limit_concurrency(|_| Some(0))
+            .drop_mode(ShutdownMode::FinishRunning)
+            .build(1);
+        runner.send(job('x', 1, 0)).unwrap();
+        runner.send(job('y', 2, 0)).unwrap();
+        runner.send(job('z', 1, 0)).unwrap();
+        drop(runner);
+        assert_eq!(executed("xyz"), "");
+
+        let runner = JobRunner::<Journaled>::builder()
+            .journal(&path)
+            .unwrap()
+            .build(1);
+        runner.shutdown(ShutdownMode::Drain, Some(TIMEOUT));
+        assert_eq!(executed("xyz"), "yxz");
+        fs::remove_file(&path).unwrap();
+    }
+}